@@ -0,0 +1,325 @@
+use chrono::{DateTime, Utc};
+
+use crate::next_fire::{compute_window_end, occurrences_between};
+use crate::store::Schedule;
+
+/// Cap on expanded VEVENTs per schedule, so a 10-second interval over a long
+/// horizon can't produce a multi-megabyte calendar.
+const MAX_EVENTS_PER_SCHEDULE: usize = 500;
+
+/// Render the upcoming occurrences of `schedules` from their next fire up to
+/// `horizon_end` as an RFC 5545 calendar. Returns the calendar text and the
+/// number of VEVENTs it contains.
+///
+/// Schedules that map cleanly onto an RRULE (intervals, and simple daily or
+/// weekly UTC crons) become a single recurring event; everything else is
+/// expanded into one VEVENT per occurrence.
+pub fn render_calendar(
+    schedules: &[&Schedule],
+    now: DateTime<Utc>,
+    horizon_end: DateTime<Utc>,
+) -> (String, usize) {
+    let stamp = format_utc(now);
+    let mut lines: Vec<String> = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//danibytes//Nexus Scheduler//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    let mut event_count = 0;
+
+    for s in schedules {
+        // Anchor at the stored next fire so interval schedules line up with
+        // what the timer loop will actually do. An open window's next fire is
+        // its end, so look past it for the next start.
+        let next_fire = match &s.active_window {
+            Some(w) => Some(w.ends_at.as_str()),
+            None => s.next_fire.as_deref(),
+        };
+        let Some(next) = next_fire.and_then(|ts| ts.parse::<DateTime<Utc>>().ok()) else {
+            continue;
+        };
+        let occurrences =
+            occurrences_between(s, next.max(now), horizon_end, MAX_EVENTS_PER_SCHEDULE);
+        let Some(first) = occurrences.first() else {
+            continue;
+        };
+
+        match rrule_for(s) {
+            Some(rule) if occurrences.len() > 1 => {
+                push_event(
+                    &mut lines,
+                    s,
+                    &format!("{}@nexus-scheduler", s.id),
+                    &stamp,
+                    *first,
+                    Some(format!("{rule};UNTIL={}", format_utc(horizon_end))),
+                );
+                event_count += 1;
+            }
+            _ => {
+                for t in &occurrences {
+                    push_event(
+                        &mut lines,
+                        s,
+                        &format!("{}-{}@nexus-scheduler", s.id, t.timestamp()),
+                        &stamp,
+                        *t,
+                        None,
+                    );
+                    event_count += 1;
+                }
+            }
+        }
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut out = String::new();
+    for line in lines {
+        out.push_str(&fold_line(&line));
+        out.push_str("\r\n");
+    }
+    (out, event_count)
+}
+
+fn push_event(
+    lines: &mut Vec<String>,
+    schedule: &Schedule,
+    uid: &str,
+    stamp: &str,
+    start: DateTime<Utc>,
+    rrule: Option<String>,
+) {
+    lines.push("BEGIN:VEVENT".to_string());
+    lines.push(format!("UID:{uid}"));
+    lines.push(format!("DTSTAMP:{stamp}"));
+    lines.push(format!("DTSTART:{}", format_utc(start)));
+    if schedule.schedule_type == "window" {
+        if let Some(end) = compute_window_end(schedule, start) {
+            lines.push(format!("DTEND:{}", format_utc(end)));
+        }
+    }
+    if let Some(rule) = rrule {
+        lines.push(format!("RRULE:{rule}"));
+    }
+    lines.push(format!("SUMMARY:{}", escape_text(&schedule.name)));
+    lines.push(format!(
+        "DESCRIPTION:{}",
+        escape_text(&format!("Publishes {}", schedule.event_type))
+    ));
+    lines.push("TRANSP:TRANSPARENT".to_string());
+    lines.push("END:VEVENT".to_string());
+}
+
+/// Build the recurrence part of an RRULE (without UNTIL) for schedules whose
+/// semantics an RRULE can express exactly.
+fn rrule_for(schedule: &Schedule) -> Option<String> {
+    // Jittered fires don't repeat at a fixed offset.
+    if schedule.jitter_seconds.unwrap_or(0) > 0 {
+        return None;
+    }
+    match schedule.schedule_type.as_str() {
+        "interval" => {
+            let secs = schedule.interval_seconds?;
+            Some(match secs {
+                s if s % 86_400 == 0 => format!("FREQ=DAILY;INTERVAL={}", s / 86_400),
+                s if s % 3_600 == 0 => format!("FREQ=HOURLY;INTERVAL={}", s / 3_600),
+                s if s % 60 == 0 => format!("FREQ=MINUTELY;INTERVAL={}", s / 60),
+                s => format!("FREQ=SECONDLY;INTERVAL={s}"),
+            })
+        }
+        // DTSTART is emitted in UTC, so a cron in any other zone would drift
+        // across DST changes — expand those instead.
+        "cron" if schedule.timezone == "UTC" => cron_rrule(schedule.cron_expression.as_deref()?),
+        _ => None,
+    }
+}
+
+/// Translate a 5-field cron of the form `M H * * <dow>` into a DAILY or
+/// WEEKLY rule. Day-of-week must be `*` or names (`MON`, `MON-FRI`,
+/// `SAT,SUN`); anything else is left for expansion.
+fn cron_rrule(expr: &str) -> Option<String> {
+    let fields: Vec<&str> = expr.split_whitespace().collect();
+    let [minute, hour, dom, month, dow] = fields.as_slice() else {
+        return None;
+    };
+    minute.parse::<u8>().ok().filter(|m| *m < 60)?;
+    hour.parse::<u8>().ok().filter(|h| *h < 24)?;
+    if *dom != "*" || *month != "*" {
+        return None;
+    }
+    if *dow == "*" {
+        return Some("FREQ=DAILY".to_string());
+    }
+
+    let mut days: Vec<usize> = Vec::new();
+    for part in dow.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (day_index(from)?, day_index(to)?);
+                if from > to {
+                    return None;
+                }
+                days.extend(from..=to);
+            }
+            None => days.push(day_index(part)?),
+        }
+    }
+    // Overlapping parts (`MON-WED,TUE`) may repeat a day in any order.
+    days.sort_unstable();
+    days.dedup();
+    let days: Vec<&str> = days.into_iter().map(|d| ICAL_DAYS[d]).collect();
+    Some(format!("FREQ=WEEKLY;BYDAY={}", days.join(",")))
+}
+
+const CRON_DAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+const ICAL_DAYS: [&str; 7] = ["SU", "MO", "TU", "WE", "TH", "FR", "SA"];
+
+fn day_index(name: &str) -> Option<usize> {
+    let upper = name.to_ascii_uppercase();
+    CRON_DAYS.iter().position(|d| *d == upper)
+}
+
+fn format_utc(t: DateTime<Utc>) -> String {
+    t.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escape a TEXT value per RFC 5545 §3.3.11.
+fn escape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Fold a content line at 75 octets (RFC 5545 §3.1), never splitting a
+/// UTF-8 sequence.
+fn fold_line(line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + line.len() / 74 * 3);
+    let mut width = 0;
+    for c in line.chars() {
+        let len = c.len_utf8();
+        if width + len > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += len;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_schedule;
+    use serde_json::json;
+
+    fn at(ts: &str) -> DateTime<Utc> {
+        ts.parse().unwrap()
+    }
+
+    #[test]
+    fn cron_rrule_translation() {
+        assert_eq!(cron_rrule("30 9 * * *").as_deref(), Some("FREQ=DAILY"));
+        assert_eq!(
+            cron_rrule("0 9 * * MON-FRI").as_deref(),
+            Some("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR")
+        );
+        assert_eq!(
+            cron_rrule("0 9 * * sat,MON-WED,TUE,SUN").as_deref(),
+            Some("FREQ=WEEKLY;BYDAY=SU,MO,TU,WE,SA")
+        );
+        // Steps, numeric days, day-of-month and reversed ranges are expanded.
+        assert_eq!(cron_rrule("*/15 9 * * *"), None);
+        assert_eq!(cron_rrule("0 9 * * 1-5"), None);
+        assert_eq!(cron_rrule("0 9 1 * *"), None);
+        assert_eq!(cron_rrule("0 9 * * FRI-MON"), None);
+        assert_eq!(cron_rrule("0 0 9 * * *"), None);
+    }
+
+    #[test]
+    fn interval_and_cron_become_one_recurring_event() {
+        let now = at("2026-03-01T00:00:00Z");
+        let horizon = at("2026-03-08T00:00:00Z");
+        let mut interval = test_schedule("interval", json!({ "interval_seconds": 7200 }));
+        interval.next_fire = Some("2026-03-01T01:00:00Z".into());
+        let mut cron = test_schedule("cron", json!({ "cron_expression": "0 9 * * MON-FRI" }));
+        cron.id = "sch_cron".into();
+        cron.next_fire = Some("2026-03-02T09:00:00Z".into());
+
+        let (ics, count) = render_calendar(&[&interval, &cron], now, horizon);
+        assert_eq!(count, 2);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.contains("UID:sch_test@nexus-scheduler\r\n"));
+        assert!(ics.contains("DTSTART:20260301T010000Z\r\n"));
+        assert!(ics.contains("RRULE:FREQ=HOURLY;INTERVAL=2;UNTIL=20260308T000000Z\r\n"));
+        assert!(ics.contains("DTSTART:20260302T090000Z\r\n"));
+        assert!(ics.contains("RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;UNTIL=20260308T000000Z\r\n"));
+    }
+
+    #[test]
+    fn non_utc_and_jittered_crons_are_expanded() {
+        let now = at("2026-03-01T00:00:00Z");
+        let horizon = at("2026-03-04T00:00:00Z");
+        let mut cron = test_schedule(
+            "cron",
+            json!({ "cron_expression": "0 9 * * *", "timezone": "Europe/Berlin" }),
+        );
+        cron.next_fire = Some("2026-03-01T08:00:00Z".into());
+
+        let (ics, count) = render_calendar(&[&cron], now, horizon);
+        assert_eq!(count, 3);
+        assert!(!ics.contains("RRULE"));
+        for (uid, start) in [
+            ("1772352000", "20260301T080000Z"),
+            ("1772438400", "20260302T080000Z"),
+            ("1772524800", "20260303T080000Z"),
+        ] {
+            assert!(ics.contains(&format!("UID:sch_test-{uid}@nexus-scheduler\r\n")));
+            assert!(ics.contains(&format!("DTSTART:{start}\r\n")));
+        }
+
+        cron.timezone = "UTC".into();
+        cron.jitter_seconds = Some(60);
+        let (ics, count) = render_calendar(&[&cron], now, horizon);
+        assert_eq!(count, 3);
+        assert!(!ics.contains("RRULE"));
+    }
+
+    #[test]
+    fn window_events_carry_their_end() {
+        let now = at("2026-03-01T00:00:00Z");
+        let horizon = at("2026-03-03T00:00:00Z");
+        let mut window = test_schedule(
+            "window",
+            json!({ "cron_expression": "0 22 * * *", "window_end_expression": "0 6 * * *" }),
+        );
+        window.next_fire = Some("2026-03-01T22:00:00Z".into());
+
+        let (ics, count) = render_calendar(&[&window], now, horizon);
+        assert_eq!(count, 2);
+        assert!(ics.contains("DTSTART:20260301T220000Z\r\nDTEND:20260302T060000Z\r\n"));
+        assert!(ics.contains("DTSTART:20260302T220000Z\r\nDTEND:20260303T060000Z\r\n"));
+    }
+
+    #[test]
+    fn text_is_escaped_and_folded() {
+        assert_eq!(escape_text("a,b;c\\d\ne\r"), "a\\,b\\;c\\\\d\\ne");
+        let folded = fold_line(&format!("SUMMARY:{}", "é".repeat(40)));
+        let lines: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|l| l.len() <= 75));
+        assert!(lines[1].starts_with(' '));
+    }
+}
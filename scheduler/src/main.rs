@@ -1,3 +1,4 @@
+mod ics;
 mod next_fire;
 mod store;
 
@@ -9,8 +10,8 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{mpsc, Mutex};
 
-use next_fire::compute_next_fire;
-use store::{FireRecord, Schedule, ScheduleStore};
+use next_fire::{compute_next_fire, compute_window_end, occurrences_between, SOLAR_EVENTS};
+use store::{ActiveWindow, FireRecord, Schedule, ScheduleStore};
 
 // ---------------------------------------------------------------------------
 // JSON-RPC types
@@ -132,12 +133,7 @@ async fn main() {
         let stdin = io::stdin();
         let mut lines = stdin.lock().lines();
 
-        loop {
-            let line = match lines.next() {
-                Some(Ok(l)) => l,
-                _ => break,
-            };
-
+        while let Some(Ok(line)) = lines.next() {
             if line.trim().is_empty() {
                 continue;
             }
@@ -256,6 +252,9 @@ async fn handle_execute(
     let result = match operation {
         "trigger_now" => op_trigger_now(&input, store, stdout).await,
         "get_fire_history" => op_get_fire_history(&input, store).await,
+        "backfill" => op_backfill(&input, store, stdout).await,
+        "export_ics" => op_export_ics(&input, store).await,
+        "is_window_active" => op_is_window_active(&input, store).await,
         _ => Err(format!("Unknown operation: {operation}")),
     };
 
@@ -281,6 +280,9 @@ async fn op_trigger_now(
         let s = st
             .get(&schedule_id)
             .ok_or_else(|| format!("schedule not found: {schedule_id}"))?;
+        if s.schedule_type == "window" {
+            return Err("trigger_now is not supported for window schedules".to_string());
+        }
         (s.event_type.clone(), s.event_data.clone(), s.name.clone())
     };
 
@@ -309,6 +311,9 @@ async fn op_trigger_now(
     let record = FireRecord {
         fire_time: fire_time.clone(),
         event_id: event_id.clone(),
+        backfill: false,
+        window_id: None,
+        window_phase: None,
     };
 
     {
@@ -347,6 +352,191 @@ async fn op_get_fire_history(
     Ok(serde_json::json!({ "fires": fires }))
 }
 
+/// Upper bound on occurrences published by a single backfill request. All
+/// of them have to fit in the schedule's history.
+const MAX_BACKFILL: usize = store::MAX_BACKFILL_HISTORY;
+const DEFAULT_BACKFILL_THROTTLE_MS: u64 = 200;
+
+async fn op_backfill(
+    input: &Value,
+    store: Arc<Mutex<ScheduleStore>>,
+    stdout: StdoutTx,
+) -> Result<Value, String> {
+    let schedule_id = input
+        .get("schedule_id")
+        .and_then(|v| v.as_str())
+        .ok_or("missing required field: schedule_id")?
+        .to_string();
+    let start = input
+        .get("start")
+        .and_then(|v| v.as_str())
+        .ok_or("missing required field: start")?
+        .parse::<chrono::DateTime<Utc>>()
+        .map_err(|e| format!("invalid start: {e}"))?;
+    let now = Utc::now();
+    let end = match input.get("end").and_then(|v| v.as_str()) {
+        Some(ts) => ts
+            .parse::<chrono::DateTime<Utc>>()
+            .map_err(|e| format!("invalid end: {e}"))?,
+        None => now,
+    };
+    if end <= start {
+        return Err("end must be after start".to_string());
+    }
+    if end > now {
+        return Err("end must not be in the future".to_string());
+    }
+    let throttle_ms = input
+        .get("throttle_ms")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_BACKFILL_THROTTLE_MS);
+
+    let (event_type, event_data, schedule_name, occurrences) = {
+        let st = store.lock().await;
+        let s = st
+            .get(&schedule_id)
+            .ok_or_else(|| format!("schedule not found: {schedule_id}"))?;
+        if s.schedule_type == "window" {
+            return Err("backfill is not supported for window schedules".to_string());
+        }
+        let occurrences = occurrences_between(s, start, end, MAX_BACKFILL + 1);
+        (
+            s.event_type.clone(),
+            s.event_data.clone(),
+            s.name.clone(),
+            occurrences,
+        )
+    };
+
+    if occurrences.len() > MAX_BACKFILL {
+        return Err(format!(
+            "range has more than {MAX_BACKFILL} occurrences; narrow start/end"
+        ));
+    }
+
+    let count = occurrences.len();
+    let first = occurrences.first().map(|t| t.to_rfc3339());
+    let last = occurrences.last().map(|t| t.to_rfc3339());
+
+    // Publish in the background so a long range doesn't stall the host's
+    // request loop; the throttle keeps downstream consumers from being flooded.
+    tokio::spawn(async move {
+        for (i, occurrence) in occurrences.into_iter().enumerate() {
+            if i > 0 && throttle_ms > 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(throttle_ms)).await;
+            }
+
+            let fire_time = occurrence.to_rfc3339();
+            let event_id = uuid::Uuid::new_v4().to_string();
+            let ipc_id = (uuid::Uuid::new_v4().as_u128() % 1_000_000) as u64 + 2000;
+
+            let publish_req = serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "event.publish",
+                "params": {
+                    "type": event_type,
+                    "data": {
+                        "schedule_id": schedule_id,
+                        "schedule_name": schedule_name,
+                        "fire_time": fire_time,
+                        "event_data": event_data,
+                        "backfill": true,
+                    },
+                    "subject": schedule_id,
+                },
+                "id": ipc_id,
+            });
+
+            send_line(&stdout, serde_json::to_string(&publish_req).unwrap()).await;
+
+            let record = FireRecord {
+                fire_time,
+                event_id,
+                backfill: true,
+                window_id: None,
+                window_phase: None,
+            };
+            let st = store.lock().await;
+            if let Err(e) = st.append_history(&schedule_id, record).await {
+                eprintln!("scheduler: history write: {e}");
+            }
+        }
+        eprintln!("scheduler: backfill of {schedule_id} complete ({count} fires)");
+    });
+
+    Ok(serde_json::json!({
+        "queued": count,
+        "first_fire_time": first,
+        "last_fire_time": last,
+    }))
+}
+
+async fn op_is_window_active(
+    input: &Value,
+    store: Arc<Mutex<ScheduleStore>>,
+) -> Result<Value, String> {
+    let schedule_id = input
+        .get("schedule_id")
+        .and_then(|v| v.as_str())
+        .ok_or("missing required field: schedule_id")?;
+
+    let st = store.lock().await;
+    let s = st
+        .get(schedule_id)
+        .ok_or_else(|| format!("schedule not found: {schedule_id}"))?;
+    if s.schedule_type != "window" {
+        return Err(format!("schedule {schedule_id} is not a window schedule"));
+    }
+
+    Ok(match &s.active_window {
+        Some(w) => serde_json::json!({
+            "active": true,
+            "window_id": w.id,
+            "started_at": w.started_at,
+            "ends_at": w.ends_at,
+        }),
+        None => serde_json::json!({
+            "active": false,
+            "next_start": s.next_fire,
+        }),
+    })
+}
+
+const DEFAULT_ICS_HORIZON_DAYS: i64 = 30;
+const MAX_ICS_HORIZON_DAYS: i64 = 366;
+
+async fn op_export_ics(input: &Value, store: Arc<Mutex<ScheduleStore>>) -> Result<Value, String> {
+    let horizon_days = input
+        .get("horizon_days")
+        .and_then(|v| v.as_i64())
+        .unwrap_or(DEFAULT_ICS_HORIZON_DAYS);
+    if !(1..=MAX_ICS_HORIZON_DAYS).contains(&horizon_days) {
+        return Err(format!(
+            "horizon_days must be between 1 and {MAX_ICS_HORIZON_DAYS}"
+        ));
+    }
+    let schedule_id = input.get("schedule_id").and_then(|v| v.as_str());
+
+    let now = Utc::now();
+    let horizon_end = now + chrono::Duration::days(horizon_days);
+
+    let st = store.lock().await;
+    let schedules: Vec<&Schedule> = match schedule_id {
+        Some(id) => vec![st
+            .get(id)
+            .ok_or_else(|| format!("schedule not found: {id}"))?],
+        None => st.list().iter().filter(|s| s.status == "active").collect(),
+    };
+
+    let (calendar, event_count) = ics::render_calendar(&schedules, now, horizon_end);
+
+    Ok(serde_json::json!({
+        "ics": calendar,
+        "event_count": event_count,
+        "horizon_end": horizon_end.to_rfc3339(),
+    }))
+}
+
 // ---------------------------------------------------------------------------
 // Resource CRUD handlers
 // ---------------------------------------------------------------------------
@@ -444,6 +634,10 @@ async fn handle_resources_create(
         }
     }
 
+    if let Err(e) = validate_solar_fields(&data) {
+        return err_response(req.id, -32000, e);
+    }
+
     let now = Utc::now();
     let id = format!("sch_{}", &uuid::Uuid::new_v4().to_string()[..8]);
 
@@ -470,6 +664,20 @@ async fn handle_resources_create(
             .and_then(|v| v.as_str())
             .unwrap_or("UTC")
             .to_string(),
+        latitude: data.get("latitude").and_then(|v| v.as_f64()),
+        longitude: data.get("longitude").and_then(|v| v.as_f64()),
+        solar_event: data
+            .get("solar_event")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        offset_minutes: data.get("offset_minutes").and_then(|v| v.as_i64()),
+        window_duration_seconds: data.get("window_duration_seconds").and_then(|v| v.as_u64()),
+        window_end_expression: data
+            .get("window_end_expression")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        jitter_seconds: data.get("jitter_seconds").and_then(|v| v.as_u64()),
+        active_window: None,
         status: "active".to_string(),
         last_fired: None,
         next_fire: None,
@@ -477,6 +685,10 @@ async fn handle_resources_create(
         created_at: now.to_rfc3339(),
     };
 
+    if let Err(e) = validate_schedule(&schedule) {
+        return err_response(req.id, -32000, e);
+    }
+
     schedule.next_fire = compute_next_fire(&schedule, now);
 
     let mut st = store.lock().await;
@@ -515,8 +727,12 @@ async fn handle_resources_update(
         }
     }
 
+    if let Err(e) = validate_solar_fields(&data) {
+        return err_response(req.id, -32000, e);
+    }
+
     let mut st = store.lock().await;
-    match st.update(&id, data) {
+    match st.update(&id, data, validate_schedule) {
         Ok(mut updated) => {
             let now = Utc::now();
             updated.next_fire = compute_next_fire(&updated, now);
@@ -533,6 +749,90 @@ async fn handle_resources_update(
     }
 }
 
+/// Range-check whichever solar fields are present in a create/update payload.
+fn validate_solar_fields(data: &Value) -> Result<(), String> {
+    if let Some(v) = data.get("latitude") {
+        match v.as_f64() {
+            Some(lat) if (-90.0..=90.0).contains(&lat) => {}
+            _ => return Err("latitude must be between -90 and 90".to_string()),
+        }
+    }
+    if let Some(v) = data.get("longitude") {
+        match v.as_f64() {
+            Some(lon) if (-180.0..=180.0).contains(&lon) => {}
+            _ => return Err("longitude must be between -180 and 180".to_string()),
+        }
+    }
+    if let Some(v) = data.get("solar_event") {
+        match v.as_str() {
+            Some(e) if SOLAR_EVENTS.contains(&e) => {}
+            _ => {
+                return Err(format!(
+                    "solar_event must be one of: {}",
+                    SOLAR_EVENTS.join(", ")
+                ))
+            }
+        }
+    }
+    if let Some(v) = data.get("offset_minutes") {
+        match v.as_i64() {
+            Some(m) if m.abs() <= 720 => {}
+            _ => return Err("offset_minutes must be between -720 and 720".to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Check the fields whose meaning depends on the schedule type, on the
+/// schedule as it will be stored.
+fn validate_schedule(s: &Schedule) -> Result<(), String> {
+    validate_window_fields(s)?;
+    if s.schedule_type == "solar" {
+        let required = [
+            ("latitude", s.latitude.is_some()),
+            ("longitude", s.longitude.is_some()),
+            ("solar_event", s.solar_event.is_some()),
+        ];
+        if let Some((field, _)) = required.iter().find(|(_, present)| !present) {
+            return Err(format!("missing required field: {field}"));
+        }
+    }
+    // Only cron and solar fires are shifted; interval, once and window
+    // schedules would silently ignore it, and random_window already spreads
+    // fires across its window.
+    if s.jitter_seconds.unwrap_or(0) > 0
+        && s.schedule_type != "cron"
+        && s.schedule_type != "solar"
+    {
+        return Err(format!(
+            "jitter_seconds is only supported for cron and solar schedules, not {}",
+            s.schedule_type
+        ));
+    }
+    Ok(())
+}
+
+/// Window and random_window schedules open on their cron expression and
+/// close after exactly one of a duration or an end expression.
+fn validate_window_fields(s: &Schedule) -> Result<(), String> {
+    if s.schedule_type != "window" && s.schedule_type != "random_window" {
+        return Ok(());
+    }
+    if s.cron_expression.is_none() {
+        return Err("missing required field: cron_expression".to_string());
+    }
+    let has_duration = s.window_duration_seconds.is_some_and(|d| d > 0);
+    let has_end = s.window_end_expression.is_some();
+    if has_duration == has_end {
+        return Err(format!(
+            "{} schedules need exactly one of \
+             window_duration_seconds or window_end_expression",
+            s.schedule_type
+        ));
+    }
+    Ok(())
+}
+
 async fn handle_resources_delete(
     req: &JsonRpcRequest,
     store: Arc<Mutex<ScheduleStore>>,
@@ -602,6 +902,10 @@ async fn fire_schedule(
     let (event_type, event_data, schedule_name, schedule_type) = {
         let st = store.lock().await;
         match st.get(schedule_id) {
+            Some(s) if s.schedule_type == "window" => {
+                drop(st);
+                return fire_window(schedule_id, store, stdout, ipc_id_counter, now).await;
+            }
             Some(s) => (
                 s.event_type.clone(),
                 s.event_data.clone(),
@@ -653,6 +957,9 @@ async fn fire_schedule(
         let record = FireRecord {
             fire_time: fire_time.clone(),
             event_id,
+            backfill: false,
+            window_id: None,
+            window_phase: None,
         };
 
         if let Err(e) = st.save().await {
@@ -663,3 +970,380 @@ async fn fire_schedule(
         }
     }
 }
+
+/// Open or close a window schedule. A `.start` event opens a new window and
+/// schedules its end; the matching `.end` carries the same `window_id`.
+async fn fire_window(
+    schedule_id: &str,
+    store: &Arc<Mutex<ScheduleStore>>,
+    stdout: &StdoutTx,
+    ipc_id_counter: &Arc<std::sync::atomic::AtomicU64>,
+    now: chrono::DateTime<Utc>,
+) {
+    let mut st = store.lock().await;
+    let Some(s) = st.get_mut(schedule_id) else {
+        return;
+    };
+
+    let fire_time = now.to_rfc3339();
+    let (phase, window) = match s.active_window.take() {
+        Some(w) => ("end", w),
+        None => {
+            let Some(ends_at) = compute_window_end(s, now) else {
+                eprintln!("scheduler: window {schedule_id} has no computable end");
+                s.next_fire = None;
+                if let Err(e) = st.save().await {
+                    eprintln!("scheduler: save after fire: {e}");
+                }
+                return;
+            };
+            let w = ActiveWindow {
+                id: format!("win_{}", &uuid::Uuid::new_v4().to_string()[..8]),
+                started_at: fire_time.clone(),
+                ends_at: ends_at.to_rfc3339(),
+            };
+            s.active_window = Some(w.clone());
+            ("start", w)
+        }
+    };
+
+    let event_id = uuid::Uuid::new_v4().to_string();
+    let ipc_id = ipc_id_counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+    let publish_req = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "event.publish",
+        "params": {
+            "type": format!("{}.{phase}", s.event_type),
+            "data": {
+                "schedule_id": schedule_id,
+                "schedule_name": s.name,
+                "fire_time": fire_time,
+                "event_data": s.event_data,
+                "window_id": window.id,
+                "window_start": window.started_at,
+                "window_end": window.ends_at,
+            },
+            "subject": schedule_id,
+        },
+        "id": ipc_id,
+    });
+
+    send_line(stdout, serde_json::to_string(&publish_req).unwrap()).await;
+
+    eprintln!(
+        "scheduler: window {phase} {schedule_id} ({}) id={} at {fire_time}",
+        s.name, window.id
+    );
+
+    s.last_fired = Some(fire_time.clone());
+    s.fire_count += 1;
+    s.next_fire = compute_next_fire(s, now);
+
+    let record = FireRecord {
+        fire_time,
+        event_id,
+        backfill: false,
+        window_id: Some(window.id),
+        window_phase: Some(phase.to_string()),
+    };
+
+    if let Err(e) = st.save().await {
+        eprintln!("scheduler: save after fire: {e}");
+    }
+    if let Err(e) = st.append_history(schedule_id, record).await {
+        eprintln!("scheduler: history write: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use store::test_schedule;
+
+    fn temp_store(schedule: Schedule) -> (Arc<Mutex<ScheduleStore>>, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("scheduler-test-{}", uuid::Uuid::new_v4()));
+        let mut store = ScheduleStore::new(dir.clone());
+        store.create(schedule).unwrap();
+        (Arc::new(Mutex::new(store)), dir)
+    }
+
+    #[tokio::test]
+    async fn backfill_publishes_marked_fires_with_throttle() {
+        let schedule = test_schedule("interval", serde_json::json!({ "interval_seconds": 10 }));
+        let (store, dir) = temp_store(schedule);
+        let (tx, mut rx) = mpsc::channel(16);
+
+        let input = serde_json::json!({
+            "schedule_id": "sch_test",
+            "start": "2026-01-01T00:00:00Z",
+            "end": "2026-01-01T00:00:25Z",
+            "throttle_ms": 40,
+        });
+        let started = std::time::Instant::now();
+        let result = op_backfill(&input, store.clone(), tx).await.unwrap();
+        assert_eq!(result["queued"], 3);
+        assert_eq!(result["first_fire_time"], "2026-01-01T00:00:00+00:00");
+        assert_eq!(result["last_fire_time"], "2026-01-01T00:00:20+00:00");
+
+        let mut fire_times = Vec::new();
+        for _ in 0..3 {
+            let line = rx.recv().await.unwrap();
+            let req: Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(req["method"], "event.publish");
+            assert_eq!(req["params"]["data"]["backfill"], true);
+            fire_times.push(req["params"]["data"]["fire_time"].as_str().unwrap().to_string());
+        }
+        assert!(started.elapsed() >= std::time::Duration::from_millis(80));
+        assert_eq!(
+            fire_times,
+            [
+                "2026-01-01T00:00:00+00:00",
+                "2026-01-01T00:00:10+00:00",
+                "2026-01-01T00:00:20+00:00",
+            ]
+        );
+
+        // The last history write happens after the last publish.
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let history = store.lock().await.get_history("sch_test").await.unwrap();
+        assert_eq!(history.len(), 3);
+        assert!(history.iter().all(|r| r.backfill));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn long_backfill_is_kept_in_history_beside_real_fires() {
+        let schedule = test_schedule("interval", serde_json::json!({ "interval_seconds": 3600 }));
+        let (store, dir) = temp_store(schedule);
+        let (tx, mut rx) = mpsc::channel(16);
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        let live = FireRecord {
+            fire_time: "2026-01-06T00:00:00+00:00".into(),
+            event_id: "evt_live".into(),
+            backfill: false,
+            window_id: None,
+            window_phase: None,
+        };
+        store.lock().await.append_history("sch_test", live).await.unwrap();
+
+        // Hourly over five days.
+        let input = serde_json::json!({
+            "schedule_id": "sch_test",
+            "start": "2026-01-01T00:00:00Z",
+            "end": "2026-01-05T23:59:59Z",
+            "throttle_ms": 0,
+        });
+        let result = op_backfill(&input, store.clone(), tx).await.unwrap();
+        assert_eq!(result["queued"], 120);
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        let history = loop {
+            let history = store.lock().await.get_history("sch_test").await.unwrap();
+            if history.len() == 121 || std::time::Instant::now() > deadline {
+                break history;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        };
+        assert_eq!(history.len(), 121);
+        assert_eq!(history.iter().filter(|r| r.backfill).count(), 120);
+        assert_eq!(history[0].event_id, "evt_live");
+        assert_eq!(history[1].fire_time, "2026-01-01T00:00:00+00:00");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn backfill_rejects_oversized_and_invalid_ranges() {
+        let schedule = test_schedule("interval", serde_json::json!({ "interval_seconds": 10 }));
+        let (store, dir) = temp_store(schedule);
+        let (tx, mut rx) = mpsc::channel(16);
+
+        // 1001 occurrences at 10s spacing.
+        let input = serde_json::json!({
+            "schedule_id": "sch_test",
+            "start": "2026-01-01T00:00:00Z",
+            "end": "2026-01-01T02:46:40Z",
+        });
+        let err = op_backfill(&input, store.clone(), tx.clone()).await.unwrap_err();
+        assert!(err.contains(&MAX_BACKFILL.to_string()), "{err}");
+
+        let input = serde_json::json!({
+            "schedule_id": "sch_test",
+            "start": "2026-01-01T01:00:00Z",
+            "end": "2026-01-01T00:00:00Z",
+        });
+        assert!(op_backfill(&input, store.clone(), tx.clone()).await.is_err());
+
+        let future = (Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+        let input = serde_json::json!({
+            "schedule_id": "sch_test",
+            "start": "2026-01-01T00:00:00Z",
+            "end": future,
+        });
+        assert!(op_backfill(&input, store, tx).await.is_err());
+
+        assert!(rx.try_recv().is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    fn window_schedule_fields() -> Value {
+        serde_json::json!({
+            "cron_expression": "0 22 * * *",
+            "window_end_expression": "0 6 * * *",
+        })
+    }
+
+    fn window_schedule() -> Schedule {
+        test_schedule("window", window_schedule_fields())
+    }
+
+    async fn next_event(rx: &mut mpsc::Receiver<String>) -> Value {
+        let line = rx.recv().await.unwrap();
+        serde_json::from_str::<Value>(&line).unwrap()["params"].take()
+    }
+
+    #[tokio::test]
+    async fn window_opens_and_closes_at_its_boundaries() {
+        let (store, dir) = temp_store(window_schedule());
+        let (tx, mut rx) = mpsc::channel(16);
+        let counter = Arc::new(std::sync::atomic::AtomicU64::new(1000));
+        let input = serde_json::json!({ "schedule_id": "sch_test" });
+
+        let opened: chrono::DateTime<Utc> = "2026-03-01T22:00:00Z".parse().unwrap();
+        fire_schedule("sch_test", &store, &tx, &counter, opened).await;
+        let start = next_event(&mut rx).await;
+        assert_eq!(start["type"], "test.fired.start");
+        assert_eq!(start["data"]["window_start"], "2026-03-01T22:00:00+00:00");
+        assert_eq!(start["data"]["window_end"], "2026-03-02T06:00:00+00:00");
+
+        let active = op_is_window_active(&input, store.clone()).await.unwrap();
+        assert_eq!(active["active"], true);
+        assert_eq!(active["window_id"], start["data"]["window_id"]);
+        assert_eq!(active["ends_at"], "2026-03-02T06:00:00+00:00");
+        {
+            let st = store.lock().await;
+            let s = st.get("sch_test").unwrap();
+            assert_eq!(s.next_fire.as_deref(), Some("2026-03-02T06:00:00+00:00"));
+        }
+
+        let closed: chrono::DateTime<Utc> = "2026-03-02T06:00:00Z".parse().unwrap();
+        fire_schedule("sch_test", &store, &tx, &counter, closed).await;
+        let end = next_event(&mut rx).await;
+        assert_eq!(end["type"], "test.fired.end");
+        assert_eq!(end["data"]["window_id"], start["data"]["window_id"]);
+
+        let inactive = op_is_window_active(&input, store.clone()).await.unwrap();
+        assert_eq!(inactive["active"], false);
+        assert_eq!(inactive["next_start"], "2026-03-02T22:00:00+00:00");
+
+        let history = store.lock().await.get_history("sch_test").await.unwrap();
+        let phases: Vec<_> = history.iter().map(|r| r.window_phase.as_deref()).collect();
+        assert_eq!(phases, [Some("start"), Some("end")]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn is_window_active_rejects_other_schedule_types() {
+        let schedule = test_schedule("interval", serde_json::json!({ "interval_seconds": 60 }));
+        let (store, dir) = temp_store(schedule);
+        let input = serde_json::json!({ "schedule_id": "sch_test" });
+        assert!(op_is_window_active(&input, store.clone()).await.is_err());
+        let input = serde_json::json!({ "schedule_id": "sch_missing" });
+        assert!(op_is_window_active(&input, store).await.is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn window_without_computable_end_is_persisted_as_stopped() {
+        let mut schedule = window_schedule();
+        schedule.window_end_expression = Some("not a cron".to_string());
+        schedule.next_fire = Some("2026-03-01T22:00:00+00:00".to_string());
+        let (store, dir) = temp_store(schedule);
+        let (tx, mut rx) = mpsc::channel(16);
+        let counter = Arc::new(std::sync::atomic::AtomicU64::new(1000));
+
+        let now: chrono::DateTime<Utc> = "2026-03-01T22:00:00Z".parse().unwrap();
+        fire_schedule("sch_test", &store, &tx, &counter, now).await;
+        assert!(rx.try_recv().is_err());
+
+        let mut reloaded = ScheduleStore::new(dir.clone());
+        reloaded.load().await.unwrap();
+        let s = reloaded.get("sch_test").unwrap();
+        assert!(s.next_fire.is_none());
+        assert!(s.active_window.is_none());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn window_fields_are_validated_on_update() {
+        let mut store = ScheduleStore::new(PathBuf::new());
+        store.create(window_schedule()).unwrap();
+
+        // Adding a duration alongside the end expression is ambiguous.
+        let data = serde_json::json!({ "window_duration_seconds": 3600 });
+        assert!(store.update("sch_test", data, validate_schedule).is_err());
+        let data = serde_json::json!({ "cron_expression": null });
+        assert!(store.update("sch_test", data, validate_schedule).is_err());
+        let s = store.get("sch_test").unwrap();
+        assert_eq!(s.cron_expression.as_deref(), Some("0 22 * * *"));
+        assert!(s.window_duration_seconds.is_none());
+
+        let data = serde_json::json!({
+            "window_duration_seconds": 3600,
+            "window_end_expression": null,
+        });
+        let updated = store.update("sch_test", data, validate_schedule).unwrap();
+        assert_eq!(updated.window_duration_seconds, Some(3600));
+        assert!(updated.window_end_expression.is_none());
+    }
+
+    #[test]
+    fn solar_fields_are_required_on_update() {
+        let mut store = ScheduleStore::new(PathBuf::new());
+        store
+            .create(test_schedule("cron", serde_json::json!({ "cron_expression": "0 9 * * *" })))
+            .unwrap();
+
+        let data = serde_json::json!({ "schedule_type": "solar", "solar_event": "sunrise" });
+        let err = store.update("sch_test", data, validate_schedule).err();
+        assert_eq!(err.as_deref(), Some("missing required field: latitude"));
+        assert_eq!(store.get("sch_test").unwrap().schedule_type, "cron");
+
+        let data = serde_json::json!({
+            "schedule_type": "solar",
+            "latitude": 51.5,
+            "longitude": -0.1,
+            "solar_event": "sunrise",
+        });
+        let updated = store.update("sch_test", data, validate_schedule).unwrap();
+        assert!(compute_next_fire(&updated, Utc::now()).is_some());
+    }
+
+    #[test]
+    fn jitter_is_rejected_where_it_would_be_ignored() {
+        for (schedule_type, fields) in [
+            ("interval", serde_json::json!({ "interval_seconds": 60 })),
+            ("once", serde_json::json!({ "run_at": "2026-03-01T00:00:00Z" })),
+            ("window", window_schedule_fields()),
+            ("random_window", window_schedule_fields()),
+        ] {
+            let mut s = test_schedule(schedule_type, fields);
+            assert!(validate_schedule(&s).is_ok(), "{schedule_type}");
+            s.jitter_seconds = Some(30);
+            assert!(validate_schedule(&s).is_err(), "{schedule_type}");
+            s.jitter_seconds = Some(0);
+            assert!(validate_schedule(&s).is_ok(), "{schedule_type}");
+        }
+        let mut cron = test_schedule("cron", serde_json::json!({ "cron_expression": "0 9 * * *" }));
+        cron.jitter_seconds = Some(30);
+        assert!(validate_schedule(&cron).is_ok());
+
+        // Switching a jittered cron to an interval is caught on update.
+        let mut store = ScheduleStore::new(PathBuf::new());
+        store.create(cron).unwrap();
+        let data = serde_json::json!({ "schedule_type": "interval", "interval_seconds": 60 });
+        assert!(store.update("sch_test", data, validate_schedule).is_err());
+        assert_eq!(store.get("sch_test").unwrap().schedule_type, "cron");
+    }
+}
@@ -7,8 +7,43 @@ use crate::store::Schedule;
 /// Compute the next fire time for a schedule, returning an ISO 8601 string.
 pub fn compute_next_fire(schedule: &Schedule, now: DateTime<Utc>) -> Option<String> {
     match schedule.schedule_type.as_str() {
+        "cron" | "random_window" if has_spread(schedule) => {
+            let (cron, tz) = parse_cron(schedule)?;
+            // Start from the period that contains `now`: its spread fire may
+            // still lie ahead even though the period itself has begun.
+            let now_in_tz = now.with_timezone(&tz);
+            let mut base = cron
+                .after(&now_in_tz)
+                .next_back()
+                .or_else(|| cron.after(&now_in_tz).next())?
+                .with_timezone(&Utc);
+            loop {
+                let t = apply_spread(schedule, base);
+                if t > now {
+                    return Some(t.to_rfc3339());
+                }
+                base = cron
+                    .after(&base.with_timezone(&tz))
+                    .next()?
+                    .with_timezone(&Utc);
+            }
+        }
         "cron" => compute_cron_next(schedule, now),
         "interval" => compute_interval_next(schedule, now),
+        "solar" => {
+            let lookback = chrono::Duration::seconds(schedule.jitter_seconds.unwrap_or(0) as i64);
+            std::iter::successors(solar_occurrence_after(schedule, now - lookback), |t| {
+                solar_occurrence_after(schedule, *t)
+            })
+            .map(|t| apply_spread(schedule, t))
+            .find(|t| *t > now)
+            .map(|t| t.to_rfc3339())
+        }
+        // An open window's next fire is its end; otherwise the next start.
+        "window" => match &schedule.active_window {
+            Some(w) => Some(w.ends_at.clone()),
+            None => compute_cron_next(schedule, now),
+        },
         "once" => {
             if schedule.status == "completed" {
                 None
@@ -34,7 +69,8 @@ fn normalize_cron_expr(expr: &str) -> String {
     }
 }
 
-fn compute_cron_next(schedule: &Schedule, now: DateTime<Utc>) -> Option<String> {
+/// Parse a schedule's cron expression together with its timezone.
+fn parse_cron(schedule: &Schedule) -> Option<(CronSchedule, chrono_tz::Tz)> {
     let raw_expr = schedule.cron_expression.as_deref()?;
     let expr = normalize_cron_expr(raw_expr);
 
@@ -43,6 +79,12 @@ fn compute_cron_next(schedule: &Schedule, now: DateTime<Utc>) -> Option<String>
     // Parse timezone
     let tz: chrono_tz::Tz = schedule.timezone.parse().unwrap_or(chrono_tz::UTC);
 
+    Some((cron, tz))
+}
+
+fn compute_cron_next(schedule: &Schedule, now: DateTime<Utc>) -> Option<String> {
+    let (cron, tz) = parse_cron(schedule)?;
+
     // Convert `now` to the schedule's timezone and iterate
     let now_in_tz = now.with_timezone(&tz);
     let next_in_tz = cron.after(&now_in_tz).next()?;
@@ -51,6 +93,66 @@ fn compute_cron_next(schedule: &Schedule, now: DateTime<Utc>) -> Option<String>
     Some(next_utc.to_rfc3339())
 }
 
+/// End of a window that opens at `start`: either a fixed duration later or
+/// the next match of the end expression, in the schedule's timezone.
+pub fn compute_window_end(schedule: &Schedule, start: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if let Some(secs) = schedule.window_duration_seconds {
+        return Some(start + chrono::Duration::seconds(secs as i64));
+    }
+    let expr = normalize_cron_expr(schedule.window_end_expression.as_deref()?);
+    let cron = CronSchedule::from_str(&expr).ok()?;
+    let tz: chrono_tz::Tz = schedule.timezone.parse().unwrap_or(chrono_tz::UTC);
+    let next = cron.after(&start.with_timezone(&tz)).next()?;
+    Some(next.with_timezone(&Utc))
+}
+
+// ---------------------------------------------------------------------------
+// Jitter and random-in-window fires
+//
+// Offsets are derived from a hash of the schedule id and the nominal
+// occurrence, so they're spread across schedules but stable across restarts
+// and recomputations.
+// ---------------------------------------------------------------------------
+
+/// Whether the schedule's fires are shifted away from their nominal time.
+fn has_spread(schedule: &Schedule) -> bool {
+    schedule.schedule_type == "random_window" || schedule.jitter_seconds.unwrap_or(0) > 0
+}
+
+/// Shift a nominal occurrence by its deterministic offset: up to
+/// `jitter_seconds` for cron and solar schedules, or anywhere inside the
+/// period's window for `random_window` schedules.
+fn apply_spread(schedule: &Schedule, base: DateTime<Utc>) -> DateTime<Utc> {
+    let max_offset = match schedule.schedule_type.as_str() {
+        "random_window" => match compute_window_end(schedule, base) {
+            Some(end) => (end - base).num_seconds().max(0) as u64,
+            None => 0,
+        },
+        "cron" | "solar" => schedule.jitter_seconds.unwrap_or(0),
+        _ => 0,
+    };
+    if max_offset == 0 {
+        return base;
+    }
+    let offset = spread_hash(&schedule.id, base.timestamp()) % (max_offset + 1);
+    base + chrono::Duration::seconds(offset as i64)
+}
+
+/// FNV-1a over the schedule id and occurrence timestamp. Unlike std's
+/// `DefaultHasher`, the output is fixed across Rust releases.
+fn spread_hash(schedule_id: &str, occurrence: i64) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    schedule_id
+        .as_bytes()
+        .iter()
+        .chain(occurrence.to_le_bytes().iter())
+        .fold(FNV_OFFSET, |hash, b| {
+            (hash ^ *b as u64).wrapping_mul(FNV_PRIME)
+        })
+}
+
 fn compute_interval_next(schedule: &Schedule, now: DateTime<Utc>) -> Option<String> {
     let interval_secs = schedule.interval_seconds?;
     if interval_secs < 10 {
@@ -70,3 +172,329 @@ fn compute_interval_next(schedule: &Schedule, now: DateTime<Utc>) -> Option<Stri
         Some(next.to_rfc3339())
     }
 }
+
+/// Enumerate the occurrences a schedule has in the inclusive range
+/// `[start, end]`, oldest first, stopping after `limit` entries.
+///
+/// Interval schedules are anchored at `start`, since there is no earlier
+/// fire to count from when looking at a past range.
+pub fn occurrences_between(
+    schedule: &Schedule,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    limit: usize,
+) -> Vec<DateTime<Utc>> {
+    if end < start || limit == 0 {
+        return Vec::new();
+    }
+
+    match schedule.schedule_type.as_str() {
+        "cron" | "window" | "random_window" => {
+            let Some((cron, tz)) = parse_cron(schedule) else {
+                return Vec::new();
+            };
+            // `after` is exclusive, so step back one second to include `start`.
+            let from = (start - chrono::Duration::seconds(1)).with_timezone(&tz);
+            // A spread fire from the period already underway may still land
+            // inside the range.
+            let current = if has_spread(schedule) {
+                cron.after(&from).next_back()
+            } else {
+                None
+            };
+            current
+                .into_iter()
+                .chain(cron.after(&from))
+                .map(|t| t.with_timezone(&Utc))
+                .take_while(|t| *t <= end)
+                .map(|t| apply_spread(schedule, t))
+                .filter(|t| *t >= start && *t <= end)
+                .take(limit)
+                .collect()
+        }
+        "interval" => {
+            let Some(interval_secs) = schedule.interval_seconds.filter(|s| *s >= 10) else {
+                return Vec::new();
+            };
+            let step = chrono::Duration::seconds(interval_secs as i64);
+            std::iter::successors(Some(start), |t| Some(*t + step))
+                .take_while(|t| *t <= end)
+                .take(limit)
+                .collect()
+        }
+        "solar" => {
+            let lookback =
+                chrono::Duration::seconds(schedule.jitter_seconds.unwrap_or(0) as i64 + 1);
+            std::iter::successors(solar_occurrence_after(schedule, start - lookback), |t| {
+                solar_occurrence_after(schedule, *t)
+            })
+            .take_while(|t| *t <= end)
+            .map(|t| apply_spread(schedule, t))
+            .filter(|t| *t >= start && *t <= end)
+            .take(limit)
+            .collect()
+        }
+        "once" => schedule
+            .run_at
+            .as_deref()
+            .and_then(|ts| ts.parse::<DateTime<Utc>>().ok())
+            .filter(|t| *t >= start && *t <= end)
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Solar schedules
+//
+// Uses the NOAA simplified sunrise equation, accurate to about a minute
+// outside the polar regions — plenty for automation triggers.
+// ---------------------------------------------------------------------------
+
+/// Solar events a `solar` schedule can follow.
+pub const SOLAR_EVENTS: [&str; 8] = [
+    "sunrise",
+    "sunset",
+    "civil_dawn",
+    "civil_dusk",
+    "nautical_dawn",
+    "nautical_dusk",
+    "astronomical_dawn",
+    "astronomical_dusk",
+];
+
+/// How many days ahead to search before concluding the event never happens
+/// (e.g. sunset during polar day). A year covers every seasonal cycle.
+const SOLAR_SEARCH_DAYS: i64 = 370;
+
+/// Sun altitude in degrees for the event, and whether it happens in the
+/// morning (rising) or evening (setting).
+fn solar_event_params(event: &str) -> Option<(f64, bool)> {
+    match event {
+        // -0.833° accounts for refraction and the solar disc radius.
+        "sunrise" => Some((-0.833, true)),
+        "sunset" => Some((-0.833, false)),
+        "civil_dawn" => Some((-6.0, true)),
+        "civil_dusk" => Some((-6.0, false)),
+        "nautical_dawn" => Some((-12.0, true)),
+        "nautical_dusk" => Some((-12.0, false)),
+        "astronomical_dawn" => Some((-18.0, true)),
+        "astronomical_dusk" => Some((-18.0, false)),
+        _ => None,
+    }
+}
+
+/// First occurrence of the schedule's solar event (plus its offset) strictly
+/// after `after`. Days on which the sun never crosses the event's altitude
+/// are skipped, so polar day/night just pushes the fire to the next day the
+/// event occurs; `None` if it doesn't occur within a year.
+fn solar_occurrence_after(schedule: &Schedule, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let lat = schedule.latitude?;
+    let lon = schedule.longitude?;
+    let (altitude, rising) = solar_event_params(schedule.solar_event.as_deref()?)?;
+    let offset = chrono::Duration::minutes(schedule.offset_minutes.unwrap_or(0));
+
+    // Start a day early: at far eastern longitudes an event belonging to the
+    // previous UTC date can still lie ahead of `after`.
+    let first_day = after.date_naive() - chrono::Duration::days(1);
+    first_day
+        .iter_days()
+        .take(SOLAR_SEARCH_DAYS as usize)
+        .filter_map(|day| solar_event_on(day, lat, lon, altitude, rising))
+        .map(|t| t + offset)
+        .find(|t| *t > after)
+}
+
+/// Time of the sun crossing `altitude` on the given UTC date, or `None` when
+/// it stays entirely above or below that altitude all day.
+fn solar_event_on(
+    day: chrono::NaiveDate,
+    lat: f64,
+    lon: f64,
+    altitude: f64,
+    rising: bool,
+) -> Option<DateTime<Utc>> {
+    const J2000: f64 = 2_451_545.0;
+    const UNIX_EPOCH_JD: f64 = 2_440_587.5;
+
+    let noon = day.and_hms_opt(12, 0, 0)?.and_utc();
+    let n = (noon.timestamp() as f64 / 86_400.0 + UNIX_EPOCH_JD - J2000).round();
+
+    // Mean solar time at the longitude (east positive).
+    let j_star = n + 0.0008 - lon / 360.0;
+    let m = (357.5291 + 0.98560028 * j_star)
+        .rem_euclid(360.0)
+        .to_radians();
+    let c = 1.9148 * m.sin() + 0.0200 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    let lambda = (m.to_degrees() + c + 180.0 + 102.9372)
+        .rem_euclid(360.0)
+        .to_radians();
+    let j_transit = J2000 + j_star + 0.0053 * m.sin() - 0.0069 * (2.0 * lambda).sin();
+
+    let sin_decl = lambda.sin() * 23.4397_f64.to_radians().sin();
+    let cos_decl = sin_decl.asin().cos();
+    let phi = lat.to_radians();
+    let cos_hour_angle =
+        (altitude.to_radians().sin() - phi.sin() * sin_decl) / (phi.cos() * cos_decl);
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    let jd = if rising {
+        j_transit - hour_angle / 360.0
+    } else {
+        j_transit + hour_angle / 360.0
+    };
+
+    let unix_secs = ((jd - UNIX_EPOCH_JD) * 86_400.0).round() as i64;
+    DateTime::from_timestamp(unix_secs, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_schedule;
+    use chrono::NaiveDate;
+    use serde_json::json;
+
+    const LONDON: (f64, f64) = (51.5074, -0.1278);
+    const NEW_YORK: (f64, f64) = (40.7128, -74.0060);
+    const TROMSO: (f64, f64) = (69.6496, 18.9560);
+
+    fn at(ts: &str) -> DateTime<Utc> {
+        ts.parse().unwrap()
+    }
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    /// Within three minutes of the published almanac time.
+    fn assert_near(actual: Option<DateTime<Utc>>, expected: &str) {
+        let actual = actual.expect("event occurs");
+        let diff = (actual - at(expected)).num_seconds().abs();
+        assert!(diff <= 180, "{actual} is {diff}s from {expected}");
+    }
+
+    #[test]
+    fn solar_events_on_fixed_dates() {
+        let ((lat, lon), june) = (LONDON, day(2026, 6, 21));
+        assert_near(solar_event_on(june, lat, lon, -0.833, true), "2026-06-21T03:43:00Z");
+        assert_near(solar_event_on(june, lat, lon, -0.833, false), "2026-06-21T20:21:00Z");
+
+        let ((lat, lon), december) = (NEW_YORK, day(2026, 12, 21));
+        assert_near(solar_event_on(december, lat, lon, -0.833, true), "2026-12-21T12:16:00Z");
+        assert_near(solar_event_on(december, lat, lon, -0.833, false), "2026-12-21T21:32:00Z");
+        assert_near(solar_event_on(december, lat, lon, -6.0, true), "2026-12-21T11:46:00Z");
+    }
+
+    #[test]
+    fn polar_day_and_night_have_no_sunrise() {
+        let (lat, lon) = TROMSO;
+        for date in [day(2026, 6, 21), day(2026, 12, 21)] {
+            assert_eq!(solar_event_on(date, lat, lon, -0.833, true), None);
+            assert_eq!(solar_event_on(date, lat, lon, -0.833, false), None);
+        }
+        // The sun still gets within 6° of the horizon at midwinter noon.
+        assert!(solar_event_on(day(2026, 12, 21), lat, lon, -6.0, true).is_some());
+    }
+
+    #[test]
+    fn solar_occurrence_skips_days_without_the_event() {
+        let (lat, lon) = TROMSO;
+        let s = test_schedule(
+            "solar",
+            json!({ "latitude": lat, "longitude": lon, "solar_event": "sunset" }),
+        );
+        let after = at("2026-06-21T12:00:00Z");
+        let next = solar_occurrence_after(&s, after).unwrap();
+        assert!(next > at("2026-07-15T00:00:00Z") && next < at("2026-08-01T00:00:00Z"), "{next}");
+
+        // At the pole the sun's altitude doesn't change over a day, so it
+        // never crosses on any date and there is no next fire.
+        let pole = test_schedule(
+            "solar",
+            json!({ "latitude": 90.0, "longitude": 0.0, "solar_event": "sunrise" }),
+        );
+        assert_eq!(solar_occurrence_after(&pole, after), None);
+        assert_eq!(compute_next_fire(&pole, after), None);
+    }
+
+    #[test]
+    fn solar_offset_and_next_fire() {
+        let (lat, lon) = LONDON;
+        let s = test_schedule(
+            "solar",
+            json!({
+                "latitude": lat,
+                "longitude": lon,
+                "solar_event": "sunset",
+                "offset_minutes": -30,
+            }),
+        );
+        let sunset = solar_event_on(day(2026, 6, 21), lat, lon, -0.833, false).unwrap();
+        let expected = sunset - chrono::Duration::minutes(30);
+        assert_eq!(solar_occurrence_after(&s, at("2026-06-21T12:00:00Z")), Some(expected));
+        // Strictly after: asking at the fire itself moves to the next day.
+        let next = solar_occurrence_after(&s, expected).unwrap();
+        assert_eq!(next.date_naive(), day(2026, 6, 22));
+        assert_eq!(
+            compute_next_fire(&s, at("2026-06-21T12:00:00Z")),
+            Some(expected.to_rfc3339())
+        );
+    }
+
+    #[test]
+    fn spread_hash_is_fixed() {
+        // Stored next_fire values depend on these staying put across builds.
+        assert_eq!(spread_hash("", 0), 0xa8c7_f832_281a_39c5);
+        assert_eq!(spread_hash("sch_test", 1_772_352_000), 0xd0e9_463f_6337_0e94);
+        assert_ne!(spread_hash("sch_test", 1_772_352_000), spread_hash("sch_other", 1_772_352_000));
+        assert_ne!(spread_hash("sch_test", 1_772_352_000), spread_hash("sch_test", 1_772_438_400));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds_and_is_stable() {
+        let mut s = test_schedule(
+            "cron",
+            json!({ "cron_expression": "0 * * * *", "jitter_seconds": 300 }),
+        );
+        let start = at("2026-03-01T00:00:00Z");
+        let fires = occurrences_between(&s, start, at("2026-03-08T00:00:00Z"), 1000);
+        assert!(fires.len() >= 160, "{}", fires.len());
+        let mut offsets = std::collections::HashSet::new();
+        for t in &fires {
+            let offset = t.timestamp() % 3600;
+            assert!((0..=300).contains(&offset), "{t}");
+            offsets.insert(offset);
+        }
+        // Spread, not a constant shift.
+        assert!(offsets.len() > 50);
+        assert_eq!(fires, occurrences_between(&s, start, at("2026-03-08T00:00:00Z"), 1000));
+
+        // next_fire agrees with the enumeration, even from inside a period.
+        let next = compute_next_fire(&s, fires[3] - chrono::Duration::seconds(1));
+        assert_eq!(next, Some(fires[3].to_rfc3339()));
+
+        // Another schedule with the same cron lands elsewhere.
+        s.id = "sch_other".into();
+        assert_ne!(fires, occurrences_between(&s, start, at("2026-03-08T00:00:00Z"), 1000));
+    }
+
+    #[test]
+    fn random_window_fires_inside_its_window() {
+        let s = test_schedule(
+            "random_window",
+            json!({ "cron_expression": "0 9 * * *", "window_duration_seconds": 7200 }),
+        );
+        let (start, end) = (at("2026-03-01T00:00:00Z"), at("2026-04-01T00:00:00Z"));
+        let fires = occurrences_between(&s, start, end, 100);
+        assert_eq!(fires.len(), 31);
+        for t in &fires {
+            let nominal = t.date_naive().and_hms_opt(9, 0, 0).unwrap().and_utc();
+            assert!(*t >= nominal && *t <= nominal + chrono::Duration::hours(2), "{t}");
+        }
+    }
+}
@@ -6,6 +6,9 @@ use tokio::fs;
 
 const MAX_SCHEDULES: usize = 100;
 const MAX_HISTORY: usize = 100;
+/// Backfilled fires are kept apart from `MAX_HISTORY`, so a backfill neither
+/// pushes out real fires nor loses any of its own.
+pub const MAX_BACKFILL_HISTORY: usize = 1000;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Schedule {
@@ -18,6 +21,24 @@ pub struct Schedule {
     pub interval_seconds: Option<u64>,
     pub run_at: Option<String>,
     pub timezone: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solar_event: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset_minutes: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_duration_seconds: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_end_expression: Option<String>,
+    /// Maximum random delay added to each cron or solar fire.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jitter_seconds: Option<u64>,
+    /// The currently open window, persisted so state survives a restart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_window: Option<ActiveWindow>,
     pub status: String,
     pub last_fired: Option<String>,
     pub next_fire: Option<String>,
@@ -25,10 +46,24 @@ pub struct Schedule {
     pub created_at: String,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ActiveWindow {
+    pub id: String,
+    pub started_at: String,
+    pub ends_at: String,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct FireRecord {
     pub fire_time: String,
     pub event_id: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub backfill: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_id: Option<String>,
+    /// "start" or "end" for window schedules.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_phase: Option<String>,
 }
 
 pub struct ScheduleStore {
@@ -90,9 +125,18 @@ impl ScheduleStore {
         Ok(self.schedules.last().unwrap())
     }
 
-    pub fn update(&mut self, id: &str, data: Value) -> Result<Schedule, String> {
-        let s = self.schedules.iter_mut().find(|s| s.id == id)
+    /// Apply the fields present in `data`. The merged schedule is passed to
+    /// `validate` and only stored if it passes.
+    pub fn update(
+        &mut self,
+        id: &str,
+        data: Value,
+        validate: impl FnOnce(&Schedule) -> Result<(), String>,
+    ) -> Result<Schedule, String> {
+        let stored = self.schedules.iter_mut().find(|s| s.id == id)
             .ok_or_else(|| format!("schedule not found: {id}"))?;
+        let mut updated = stored.clone();
+        let s = &mut updated;
 
         if let Some(v) = data.get("name").and_then(|v| v.as_str()) {
             s.name = v.to_string();
@@ -118,11 +162,34 @@ impl ScheduleStore {
         if let Some(v) = data.get("timezone").and_then(|v| v.as_str()) {
             s.timezone = v.to_string();
         }
+        if let Some(v) = data.get("latitude") {
+            s.latitude = v.as_f64();
+        }
+        if let Some(v) = data.get("longitude") {
+            s.longitude = v.as_f64();
+        }
+        if let Some(v) = data.get("solar_event") {
+            s.solar_event = v.as_str().map(|s| s.to_string());
+        }
+        if let Some(v) = data.get("offset_minutes") {
+            s.offset_minutes = v.as_i64();
+        }
+        if let Some(v) = data.get("window_duration_seconds") {
+            s.window_duration_seconds = v.as_u64();
+        }
+        if let Some(v) = data.get("window_end_expression") {
+            s.window_end_expression = v.as_str().map(|s| s.to_string());
+        }
+        if let Some(v) = data.get("jitter_seconds") {
+            s.jitter_seconds = v.as_u64();
+        }
         if let Some(v) = data.get("status").and_then(|v| v.as_str()) {
             s.status = v.to_string();
         }
 
-        Ok(s.clone())
+        validate(&updated)?;
+        *stored = updated.clone();
+        Ok(updated)
     }
 
     pub fn delete(&mut self, id: &str) -> Result<(), String> {
@@ -150,8 +217,14 @@ impl ScheduleStore {
         };
 
         entries.push_back(record);
-        while entries.len() > MAX_HISTORY {
-            entries.pop_front();
+        for (backfill, limit) in [(false, MAX_HISTORY), (true, MAX_BACKFILL_HISTORY)] {
+            let kept = entries.iter().filter(|r| r.backfill == backfill).count();
+            let mut excess = kept.saturating_sub(limit);
+            entries.retain(|r| {
+                let drop = excess > 0 && r.backfill == backfill;
+                excess -= drop as usize;
+                !drop
+            });
         }
 
         let content = serde_json::to_string_pretty(&entries)
@@ -176,3 +249,24 @@ impl ScheduleStore {
         Ok(entries.into_iter().collect())
     }
 }
+
+/// A schedule for tests: `fields` are laid over an active UTC schedule of the
+/// given type.
+#[cfg(test)]
+pub fn test_schedule(schedule_type: &str, fields: Value) -> Schedule {
+    let mut value = serde_json::json!({
+        "id": "sch_test",
+        "name": "Test",
+        "event_type": "test.fired",
+        "event_data": {},
+        "schedule_type": schedule_type,
+        "timezone": "UTC",
+        "status": "active",
+        "fire_count": 0,
+        "created_at": "2026-01-01T00:00:00Z",
+    });
+    if let (Some(base), Value::Object(fields)) = (value.as_object_mut(), fields) {
+        base.extend(fields);
+    }
+    serde_json::from_value(value).expect("valid test schedule")
+}
@@ -0,0 +1,301 @@
+use axum::http::{header, HeaderMap};
+use base64::Engine as _;
+use serde_json::{Map, Value};
+use std::io::Read;
+
+/// Decode a request body into the value stored and published for it, based
+/// on `Content-Encoding` and `Content-Type`:
+/// - gzip, deflate and br encodings are undone first, producing at most
+///   `max_decoded` bytes.
+/// - JSON (`*/json`, `*+json`) is parsed.
+/// - `application/x-www-form-urlencoded` and `multipart/form-data` become
+///   objects; repeated names collect into arrays and uploaded files are kept
+///   as base64.
+/// - Text (`text/*`, XML) is kept as a string.
+/// - Anything else, or anything that fails to decode, is kept as
+///   `{content_type, encoding: "base64", data}`, with `content_encoding` if
+///   it couldn't be decompressed.
+///
+/// Without a `Content-Type`, JSON is tried, then UTF-8 text.
+pub fn decode(headers: &HeaderMap, raw: &[u8], max_decoded: u64) -> Value {
+    let content_type = header_str(headers, header::CONTENT_TYPE);
+    let media = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    let encoding = header_str(headers, header::CONTENT_ENCODING);
+    let decompressed;
+    let bytes = match decompress(&encoding, raw, max_decoded) {
+        Some(Some(bytes)) => {
+            decompressed = bytes;
+            decompressed.as_slice()
+        }
+        Some(None) => raw,
+        None => {
+            let mut kept = binary(&media, raw);
+            kept["content_encoding"] = Value::String(encoding);
+            return kept;
+        }
+    };
+
+    let text = || std::str::from_utf8(bytes).ok().map(|s| Value::String(s.to_string()));
+    let decoded = match media.as_str() {
+        "" => serde_json::from_slice(bytes).ok().or_else(text),
+        m if m.ends_with("/json") || m.ends_with("+json") => {
+            serde_json::from_slice(bytes).ok().or_else(text)
+        }
+        "application/x-www-form-urlencoded" => {
+            let mut fields = Map::new();
+            for (name, value) in form_urlencoded::parse(bytes) {
+                add_field(&mut fields, name.into_owned(), Value::String(value.into_owned()));
+            }
+            Some(Value::Object(fields))
+        }
+        "multipart/form-data" => param(&content_type, "boundary")
+            .and_then(|boundary| parse_multipart(bytes, &boundary)),
+        m if is_text(m) => text(),
+        _ => None,
+    };
+    decoded.unwrap_or_else(|| binary(&media, bytes))
+}
+
+/// Capture a query string as an object, or `None` if there isn't one.
+pub fn query_params(query: Option<&str>) -> Option<Value> {
+    let query = query.filter(|q| !q.is_empty())?;
+    let mut fields = Map::new();
+    for (name, value) in form_urlencoded::parse(query.as_bytes()) {
+        add_field(&mut fields, name.into_owned(), Value::String(value.into_owned()));
+    }
+    Some(Value::Object(fields))
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> String {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn is_text(media: &str) -> bool {
+    media.starts_with("text/") || media.ends_with("/xml") || media.ends_with("+xml")
+}
+
+/// Undo `Content-Encoding`. `Some(None)` means there was nothing to undo;
+/// `None` means an unknown encoding, corrupt data or output over `limit`.
+fn decompress(encoding: &str, raw: &[u8], limit: u64) -> Option<Option<Vec<u8>>> {
+    let codings: Vec<String> = encoding
+        .split(',')
+        .map(|c| c.trim().to_ascii_lowercase())
+        .filter(|c| !c.is_empty() && c != "identity")
+        .collect();
+    if codings.is_empty() {
+        return Some(None);
+    }
+
+    let mut data = raw.to_vec();
+    // Codings are listed in the order they were applied.
+    for coding in codings.iter().rev() {
+        let reader: Box<dyn Read + '_> = match coding.as_str() {
+            "gzip" | "x-gzip" => Box::new(flate2::read::MultiGzDecoder::new(data.as_slice())),
+            // Meant to be zlib-wrapped, but some senders send raw deflate.
+            "deflate" if is_zlib(&data) => {
+                Box::new(flate2::read::ZlibDecoder::new(data.as_slice()))
+            }
+            "deflate" => Box::new(flate2::read::DeflateDecoder::new(data.as_slice())),
+            "br" => Box::new(brotli_decompressor::Decompressor::new(data.as_slice(), 4096)),
+            _ => return None,
+        };
+        let mut out = Vec::new();
+        reader.take(limit + 1).read_to_end(&mut out).ok()?;
+        if out.len() as u64 > limit {
+            return None;
+        }
+        data = out;
+    }
+    Some(Some(data))
+}
+
+/// Whether `data` starts with a zlib header (deflate method, valid check bits).
+fn is_zlib(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && u16::from_be_bytes([*cmf, *flg]) % 31 == 0,
+        _ => false,
+    }
+}
+
+fn binary(media: &str, bytes: &[u8]) -> Value {
+    let media = if media.is_empty() { "application/octet-stream" } else { media };
+    serde_json::json!({
+        "content_type": media,
+        "encoding": "base64",
+        "data": base64::engine::general_purpose::STANDARD.encode(bytes),
+    })
+}
+
+/// Add a form field, turning repeated names into an array of values.
+fn add_field(fields: &mut Map<String, Value>, name: String, value: Value) {
+    match fields.get_mut(&name) {
+        None => {
+            fields.insert(name, value);
+        }
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+    }
+}
+
+/// A parameter of a header value such as `multipart/form-data; boundary=x`.
+fn param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|p| {
+        let (k, v) = p.split_once('=')?;
+        k.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Parse a `multipart/form-data` body into an object of its fields. Text
+/// fields are strings; files are `{filename, content_type, encoding, data}`.
+fn parse_multipart(body: &[u8], boundary: &str) -> Option<Value> {
+    let delimiter = format!("--{boundary}");
+    let separator = format!("\r\n{delimiter}");
+    let mut rest = &body[find(body, delimiter.as_bytes())? + delimiter.len()..];
+    let mut fields = Map::new();
+
+    // Each part is preceded by a delimiter; the last one is followed by "--".
+    while !rest.starts_with(b"--") {
+        rest = rest.strip_prefix(b"\r\n")?;
+        let end = find(rest, separator.as_bytes())?;
+        let part = &rest[..end];
+        rest = &rest[end + separator.len()..];
+
+        let (head, content) = match part.strip_prefix(b"\r\n") {
+            Some(content) => (&b""[..], content),
+            None => {
+                let split = find(part, b"\r\n\r\n")?;
+                (&part[..split], &part[split + 4..])
+            }
+        };
+        let head = std::str::from_utf8(head).ok()?;
+        let mut disposition = "";
+        let mut part_type = "";
+        for line in head.split("\r\n") {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            if name.trim().eq_ignore_ascii_case("content-disposition") {
+                disposition = value.trim();
+            } else if name.trim().eq_ignore_ascii_case("content-type") {
+                part_type = value.trim();
+            }
+        }
+
+        let Some(name) = param(disposition, "name") else {
+            continue;
+        };
+        let text = std::str::from_utf8(content).ok();
+        let value = match (param(disposition, "filename"), text) {
+            (None, Some(text)) => Value::String(text.to_string()),
+            (filename, _) => {
+                let media = part_type.split(';').next().unwrap_or_default().trim();
+                let mut file = binary(&media.to_ascii_lowercase(), content);
+                file["filename"] = filename.map_or(Value::Null, Value::String);
+                file
+            }
+        };
+        add_field(&mut fields, name, value);
+    }
+    Some(Value::Object(fields))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, value.parse().unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn form_fields() {
+        let h = headers(&[("content-type", "application/x-www-form-urlencoded")]);
+        let body = b"From=%2B15551234567&Body=hi+there&MediaUrl=a&MediaUrl=b";
+        assert_eq!(
+            decode(&h, body, 1024),
+            serde_json::json!({
+                "From": "+15551234567",
+                "Body": "hi there",
+                "MediaUrl": ["a", "b"],
+            })
+        );
+    }
+
+    #[test]
+    fn gzip_json() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(br#"{"ok":true}"#).unwrap();
+        let body = gz.finish().unwrap();
+        let h = headers(&[("content-type", "application/json"), ("content-encoding", "gzip")]);
+        assert_eq!(decode(&h, &body, 1024), serde_json::json!({ "ok": true }));
+
+        // Over the cap, the compressed bytes are kept as they arrived.
+        let kept = decode(&h, &body, 4);
+        assert_eq!(kept["encoding"], "base64");
+        assert_eq!(kept["content_encoding"], "gzip");
+    }
+
+    #[test]
+    fn binary_and_text() {
+        let h = headers(&[("content-type", "application/octet-stream")]);
+        assert_eq!(
+            decode(&h, &[0xff, 0x00], 1024),
+            serde_json::json!({
+                "content_type": "application/octet-stream",
+                "encoding": "base64",
+                "data": "/wA=",
+            })
+        );
+        let h = headers(&[("content-type", "application/xml; charset=utf-8")]);
+        assert_eq!(decode(&h, b"<a/>", 1024), Value::String("<a/>".into()));
+        assert_eq!(decode(&HeaderMap::new(), b"[1]", 1024), serde_json::json!([1]));
+    }
+
+    #[test]
+    fn multipart_fields_and_files() {
+        let h = headers(&[("content-type", "multipart/form-data; boundary=\"xyz\"")]);
+        let body = b"preamble\r\n--xyz\r\n\
+            Content-Disposition: form-data; name=\"subject\"\r\n\r\n\
+            Hello\r\n--xyz\r\n\
+            Content-Disposition: form-data; name=\"attachment\"; filename=\"a.bin\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n\
+            \x01\x02\r\n--xyz--\r\n";
+        let decoded = decode(&h, body, 1024);
+        assert_eq!(decoded["subject"], "Hello");
+        assert_eq!(decoded["attachment"]["filename"], "a.bin");
+        assert_eq!(decoded["attachment"]["data"], "AQI=");
+    }
+
+    #[test]
+    fn query_string() {
+        assert_eq!(query_params(None), None);
+        assert_eq!(
+            query_params(Some("a=1&a=2&b=x%20y")),
+            Some(serde_json::json!({ "a": ["1", "2"], "b": "x y" }))
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long a request waits for its reply when the webhook doesn't say.
+/// Slack gives up on slash commands after three seconds.
+pub const DEFAULT_TIMEOUT_MS: u64 = 2_500;
+
+/// Longest a request may be held open.
+const MAX_TIMEOUT_MS: u64 = 60_000;
+
+/// Hold requests open until the host replies. The event is published with a
+/// `correlation_id` in its data, and whatever handles it answers through the
+/// `reply_to_delivery` operation. If no reply arrives in time the webhook's
+/// response template is sent, or a 504 without one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncReply {
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_timeout_ms() -> u64 {
+    DEFAULT_TIMEOUT_MS
+}
+
+impl SyncReply {
+    /// Parse `sync_reply` from operation input and check it.
+    pub fn parse(value: &Value) -> Result<Self, String> {
+        let sync: SyncReply = serde_json::from_value(value.clone())
+            .map_err(|e| format!("invalid sync_reply: {e}"))?;
+        if sync.timeout_ms == 0 || sync.timeout_ms > MAX_TIMEOUT_MS {
+            return Err(format!(
+                "invalid sync_reply: timeout_ms must be between 1 and {MAX_TIMEOUT_MS}"
+            ));
+        }
+        Ok(sync)
+    }
+}
+
+/// The HTTP response the host supplied for a held request. A string body is
+/// sent as is; any other JSON value is sent as JSON.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Reply {
+    #[serde(default)]
+    pub status: Option<u16>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Option<Value>,
+}
+
+impl Reply {
+    pub fn parse(value: &Value) -> Result<Self, String> {
+        let reply: Reply =
+            serde_json::from_value(value.clone()).map_err(|e| format!("invalid reply: {e}"))?;
+        if let Some(status) = reply.status {
+            StatusCode::from_u16(status).map_err(|_| format!("invalid reply status {status}"))?;
+        }
+        for (name, value) in &reply.headers {
+            HeaderName::try_from(name.as_str())
+                .map_err(|_| format!("invalid reply header name '{name}'"))?;
+            HeaderValue::from_str(value)
+                .map_err(|_| format!("invalid value for reply header '{name}'"))?;
+        }
+        Ok(reply)
+    }
+}
+
+impl IntoResponse for Reply {
+    fn into_response(self) -> Response {
+        let status = self
+            .status
+            .and_then(|s| StatusCode::from_u16(s).ok())
+            .unwrap_or(StatusCode::OK);
+        let mut response = match self.body {
+            None | Some(Value::Null) => status.into_response(),
+            Some(Value::String(text)) => (status, text).into_response(),
+            Some(json) => (status, axum::Json(json)).into_response(),
+        };
+        let headers = response.headers_mut();
+        for (name, value) in &self.headers {
+            if let (Ok(name), Ok(value)) =
+                (HeaderName::try_from(name.as_str()), HeaderValue::from_str(value))
+            {
+                headers.insert(name, value);
+            }
+        }
+        response
+    }
+}
+
+/// Requests held open for a reply, keyed by correlation id (the delivery id).
+#[derive(Default)]
+pub struct PendingReplies {
+    waiting: Mutex<HashMap<String, oneshot::Sender<Reply>>>,
+}
+
+impl PendingReplies {
+    /// Start waiting for a reply. Register before publishing, so a fast
+    /// reply can't arrive before anyone is listening.
+    pub fn register(self: &Arc<Self>, correlation_id: &str) -> Waiter {
+        let (tx, rx) = oneshot::channel();
+        self.waiting.lock().unwrap().insert(correlation_id.to_string(), tx);
+        Waiter {
+            pending: self.clone(),
+            correlation_id: correlation_id.to_string(),
+            rx: Some(rx),
+        }
+    }
+
+    /// Hand a reply to the request waiting on it. Returns false if nothing
+    /// is waiting (unknown id, timed out or already answered).
+    pub fn resolve(&self, correlation_id: &str, reply: Reply) -> bool {
+        let Some(tx) = self.waiting.lock().unwrap().remove(correlation_id) else {
+            return false;
+        };
+        tx.send(reply).is_ok()
+    }
+}
+
+/// A registered wait. Dropping it, including when the sender hangs up and
+/// the request is abandoned, stops waiting so a late reply is refused.
+pub struct Waiter {
+    pending: Arc<PendingReplies>,
+    correlation_id: String,
+    rx: Option<oneshot::Receiver<Reply>>,
+}
+
+impl Waiter {
+    pub async fn reply(mut self, timeout: Duration) -> Option<Reply> {
+        let rx = self.rx.take()?;
+        tokio::time::timeout(timeout, rx).await.ok()?.ok()
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        self.pending.waiting.lock().unwrap().remove(&self.correlation_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn reply(body: &str) -> Reply {
+        Reply::parse(&json!({ "status": 201, "body": body })).unwrap()
+    }
+
+    #[test]
+    fn timeout_is_bounded() {
+        assert_eq!(SyncReply::parse(&json!({})).unwrap().timeout_ms, DEFAULT_TIMEOUT_MS);
+        assert!(SyncReply::parse(&json!({ "timeout_ms": 0 })).is_err());
+        assert!(SyncReply::parse(&json!({ "timeout_ms": MAX_TIMEOUT_MS })).is_ok());
+        assert!(SyncReply::parse(&json!({ "timeout_ms": MAX_TIMEOUT_MS + 1 })).is_err());
+    }
+
+    #[tokio::test]
+    async fn waiter_receives_its_reply() {
+        let pending = Arc::new(PendingReplies::default());
+        let waiter = pending.register("dlv_1");
+        let other = pending.register("dlv_2");
+        assert!(pending.resolve("dlv_1", reply("first")));
+        // Already answered.
+        assert!(!pending.resolve("dlv_1", reply("again")));
+
+        let got = waiter.reply(Duration::from_secs(1)).await.unwrap();
+        assert_eq!(got.status, Some(201));
+        assert_eq!(got.body, Some(json!("first")));
+        drop(other);
+        assert!(pending.waiting.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn late_reply_is_refused() {
+        let pending = Arc::new(PendingReplies::default());
+        let waiter = pending.register("dlv_1");
+        assert!(waiter.reply(Duration::from_millis(10)).await.is_none());
+        assert!(!pending.resolve("dlv_1", reply("late")));
+        assert!(!pending.resolve("dlv_unknown", reply("stray")));
+    }
+
+    #[tokio::test]
+    async fn abandoned_request_stops_waiting() {
+        let pending = Arc::new(PendingReplies::default());
+        let request = tokio::spawn({
+            let waiter = pending.register("dlv_1");
+            async move { waiter.reply(Duration::from_secs(30)).await }
+        });
+        request.abort();
+        let _ = request.await;
+        assert!(!pending.resolve("dlv_1", reply("late")));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::types::Webhook;
+use crate::verification::constant_time_eq;
+use axum::http::{header, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+/// Handshake modes a webhook can answer:
+/// - `slack`: signed `url_verification` POST, echo `challenge`.
+/// - `meta`: `hub.challenge` GET (Meta, WhatsApp, Instagram), checked
+///   against the webhook's `challenge_token`.
+/// - `dropbox`: `challenge` GET.
+/// - `microsoft-graph`: POST with a `validationToken` query parameter.
+pub const MODES: &[&str] = &["slack", "meta", "dropbox", "microsoft-graph"];
+
+pub fn validate_mode(mode: &str, token: Option<&str>) -> Result<(), String> {
+    if !MODES.contains(&mode) {
+        return Err(format!(
+            "invalid challenge_mode '{mode}': expected one of {}",
+            MODES.join(", ")
+        ));
+    }
+    if mode == "meta" && token.is_none_or(str::is_empty) {
+        return Err("challenge_mode 'meta' requires challenge_token".into());
+    }
+    Ok(())
+}
+
+/// A handshake to answer instead of publishing.
+pub struct Handshake {
+    /// What kind of handshake it was, for the delivery log.
+    pub kind: &'static str,
+    challenge: String,
+}
+
+impl IntoResponse for Handshake {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+                // Dropbox requires this so the echo can't be sniffed as HTML.
+                (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+            ],
+            self.challenge,
+        )
+            .into_response()
+    }
+}
+
+/// Recognise a handshake that carries no signature and so is answered
+/// before verification. `Err` holds the rejection reason for a handshake
+/// with the wrong verify token.
+pub fn unsigned(
+    wh: &Webhook,
+    method: &Method,
+    query: Option<&str>,
+) -> Option<Result<Handshake, &'static str>> {
+    let mode = wh.challenge_mode.as_deref()?;
+    let param = |name: &str| {
+        form_urlencoded::parse(query?.as_bytes())
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+    };
+    match (mode, method) {
+        ("meta", &Method::GET) => {
+            if param("hub.mode").as_deref() != Some("subscribe") {
+                return None;
+            }
+            let challenge = param("hub.challenge")?;
+            let expected = wh.challenge_token.as_deref().unwrap_or_default();
+            let token = param("hub.verify_token").unwrap_or_default();
+            if expected.is_empty() || !constant_time_eq(expected.as_bytes(), token.as_bytes()) {
+                return Some(Err("bad_challenge_token"));
+            }
+            Some(Ok(Handshake {
+                kind: "meta hub.challenge",
+                challenge,
+            }))
+        }
+        ("dropbox", &Method::GET) => Some(Ok(Handshake {
+            kind: "dropbox challenge",
+            challenge: param("challenge")?,
+        })),
+        ("microsoft-graph", &Method::POST) => Some(Ok(Handshake {
+            kind: "microsoft-graph validationToken",
+            challenge: param("validationToken")?,
+        })),
+        _ => None,
+    }
+}
+
+/// Recognise a handshake that arrives signed like a normal delivery, once
+/// it has passed verification.
+pub fn signed(wh: &Webhook, body: &serde_json::Value) -> Option<Handshake> {
+    match wh.challenge_mode.as_deref()? {
+        "slack" if body.get("type").and_then(|t| t.as_str()) == Some("url_verification") => {
+            Some(Handshake {
+                kind: "slack url_verification",
+                challenge: body.get("challenge")?.as_str()?.to_string(),
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use serde_json::json;
+
+    fn webhook(mode: &str, token: Option<&str>) -> Webhook {
+        serde_json::from_value(json!({
+            "id": "wh_test",
+            "name": "test",
+            "event_type": "com.example.test",
+            "verification_method": "none",
+            "challenge_mode": mode,
+            "challenge_token": token,
+            "status": "active",
+            "url": "https://example.com/hooks/wh_test",
+            "trigger_count": 0,
+            "last_triggered": null,
+            "created_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    async fn echoed(handshake: Handshake) -> String {
+        let response = handshake.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn modes_are_validated() {
+        assert!(validate_mode("slack", None).is_ok());
+        assert!(validate_mode("meta", Some("verify")).is_ok());
+        assert!(validate_mode("meta", None).is_err());
+        assert!(validate_mode("meta", Some("")).is_err());
+        assert!(validate_mode("github", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn meta_checks_the_verify_token() {
+        let wh = webhook("meta", Some("s3cret"));
+        let query = "hub.mode=subscribe&hub.challenge=1158201444&hub.verify_token=s3cret";
+        let handshake = unsigned(&wh, &Method::GET, Some(query)).unwrap().unwrap();
+        assert_eq!(handshake.kind, "meta hub.challenge");
+        assert_eq!(echoed(handshake).await, "1158201444");
+
+        let query = "hub.mode=subscribe&hub.challenge=1&hub.verify_token=wrong";
+        assert!(matches!(
+            unsigned(&wh, &Method::GET, Some(query)),
+            Some(Err("bad_challenge_token"))
+        ));
+        // Not a subscription handshake, or not a GET: handled as a delivery.
+        assert!(unsigned(&wh, &Method::GET, Some("hub.challenge=1")).is_none());
+        assert!(unsigned(&wh, &Method::POST, Some(query)).is_none());
+        assert!(unsigned(&wh, &Method::GET, None).is_none());
+    }
+
+    #[tokio::test]
+    async fn dropbox_and_graph_echo_the_challenge() {
+        let wh = webhook("dropbox", None);
+        let handshake = unsigned(&wh, &Method::GET, Some("challenge=abc%20123")).unwrap().unwrap();
+        assert_eq!(echoed(handshake).await, "abc 123");
+        assert!(unsigned(&wh, &Method::POST, Some("challenge=abc")).is_none());
+
+        let wh = webhook("microsoft-graph", None);
+        let query = "validationToken=Validation%3A+Testing";
+        let handshake = unsigned(&wh, &Method::POST, Some(query)).unwrap().unwrap();
+        assert_eq!(echoed(handshake).await, "Validation: Testing");
+        assert!(unsigned(&wh, &Method::POST, None).is_none());
+    }
+
+    #[tokio::test]
+    async fn slack_echoes_after_verification() {
+        let wh = webhook("slack", None);
+        assert!(unsigned(&wh, &Method::POST, None).is_none());
+        let body = json!({ "type": "url_verification", "challenge": "3eZbrw1aB" });
+        assert_eq!(echoed(signed(&wh, &body).unwrap()).await, "3eZbrw1aB");
+        assert!(signed(&wh, &json!({ "type": "event_callback" })).is_none());
+        assert!(signed(&webhook("dropbox", None), &body).is_none());
+    }
+}
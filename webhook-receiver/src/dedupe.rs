@@ -0,0 +1,194 @@
+use crate::outbox::write_json_durable;
+use crate::rules;
+use crate::types::{Delivery, Webhook};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// How long a dedupe key is remembered when the webhook doesn't say.
+/// Covers GitHub's and Shopify's retry schedules; Stripe retries for up to
+/// three days, so Stripe webhooks should set a longer window.
+pub const DEFAULT_WINDOW_SECS: u64 = 86_400;
+
+/// How long after a change the cache is written out, so a burst of requests
+/// costs one write rather than one each.
+const FLUSH_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Seen {
+    delivery_id: String,
+    expires_at: i64,
+}
+
+/// Dedupe keys of recently accepted deliveries, persisted to `dedupe.json`
+/// so a sender's retry after a restart is still recognised. Claims are
+/// written out in the background shortly after they change; a crash in
+/// between can forget the last few keys, which only risks a duplicate the
+/// outbox's at-least-once delivery already allows for.
+pub struct DedupeCache {
+    path: PathBuf,
+    seen: Mutex<HashMap<String, Seen>>,
+    changed: Notify,
+}
+
+impl DedupeCache {
+    pub fn open(data_dir: &Path) -> Result<Arc<Self>, String> {
+        std::fs::create_dir_all(data_dir)
+            .map_err(|e| format!("failed to create data dir: {e}"))?;
+        let path = data_dir.join("dedupe.json");
+        let seen = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("failed to parse dedupe cache: {e}"))?,
+            Err(_) => HashMap::new(),
+        };
+        let cache = Arc::new(Self {
+            path,
+            seen: Mutex::new(seen),
+            changed: Notify::new(),
+        });
+        tokio::spawn(cache.clone().flush_loop());
+        Ok(cache)
+    }
+
+    /// Claim `key` for `delivery_id` until `expires_at`. If an unexpired
+    /// claim exists, returns the id of the delivery that holds it.
+    pub fn claim(
+        &self,
+        key: String,
+        delivery_id: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<(), String> {
+        let mut seen = self.seen.lock().unwrap();
+        seen.retain(|_, s| s.expires_at > now);
+        if let Some(existing) = seen.get(&key) {
+            return Err(existing.delivery_id.clone());
+        }
+        seen.insert(
+            key,
+            Seen {
+                delivery_id: delivery_id.to_string(),
+                expires_at,
+            },
+        );
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Give up a claim, e.g. because the delivery couldn't be queued and the
+    /// sender will retry it.
+    pub fn release(&self, key: &str) {
+        if self.seen.lock().unwrap().remove(key).is_some() {
+            self.changed.notify_one();
+        }
+    }
+
+    /// Write the current claims to disk.
+    pub async fn flush(&self) {
+        let seen = self.seen.lock().unwrap().clone();
+        let path = self.path.clone();
+        let written = tokio::task::spawn_blocking(move || write_json_durable(&path, &seen))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r);
+        if let Err(e) = written {
+            eprintln!("webhook-receiver: failed to save dedupe cache: {e}");
+        }
+    }
+
+    async fn flush_loop(self: Arc<Self>) {
+        loop {
+            self.changed.notified().await;
+            tokio::time::sleep(FLUSH_DELAY).await;
+            self.flush().await;
+        }
+    }
+}
+
+/// Check a `dedupe_key` setting: `body_hash`, `header:<name>` or
+/// `body:<path>`.
+pub fn validate_key_source(source: &str) -> Result<(), String> {
+    if source == "body_hash" {
+        return Ok(());
+    }
+    rules::check_reference(source)
+        .map_err(|_| format!("invalid dedupe_key '{source}': expected body_hash, header:<name> or body:<path>"))
+}
+
+/// The cache key for a delivery, or `None` if the webhook doesn't dedupe or
+/// the delivery lacks the configured field.
+pub fn key_for(wh: &Webhook, delivery: &Delivery) -> Option<String> {
+    let value = match wh.dedupe_key.as_deref()? {
+        "body_hash" => delivery.body_sha256.clone()?,
+        source => match rules::resolve(source, &delivery.headers, &delivery.body)? {
+            serde_json::Value::String(s) if s.is_empty() => return None,
+            serde_json::Value::String(s) => s,
+            other => other.to_string(),
+        },
+    };
+    Some(format!("{}:{value}", wh.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("dedupe-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn claim_release_and_expiry() {
+        let dir = temp_dir();
+        let cache = DedupeCache::open(&dir).unwrap();
+
+        assert_eq!(cache.claim("wh:a".into(), "dlv_1", 100, 200), Ok(()));
+        assert_eq!(cache.claim("wh:a".into(), "dlv_2", 150, 250), Err("dlv_1".into()));
+        assert_eq!(cache.claim("wh:b".into(), "dlv_3", 150, 250), Ok(()));
+
+        // Released keys can be claimed again.
+        cache.release("wh:b");
+        assert_eq!(cache.claim("wh:b".into(), "dlv_4", 160, 260), Ok(()));
+
+        // Expired claims no longer block, and are pruned.
+        assert_eq!(cache.claim("wh:a".into(), "dlv_5", 200, 300), Ok(()));
+        assert_eq!(cache.seen.lock().unwrap().len(), 2);
+        assert_eq!(cache.claim("wh:a".into(), "dlv_6", 299, 399), Err("dlv_5".into()));
+        assert_eq!(cache.seen.lock().unwrap().len(), 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn claims_survive_a_restart_once_flushed() {
+        let dir = temp_dir();
+        let cache = DedupeCache::open(&dir).unwrap();
+        cache.claim("wh:a".into(), "dlv_1", 100, 200).unwrap();
+        cache.claim("wh:b".into(), "dlv_2", 100, 200).unwrap();
+        cache.release("wh:b");
+        // Writes are batched rather than made per claim.
+        assert!(!dir.join("dedupe.json").exists());
+        cache.flush().await;
+
+        let reopened = DedupeCache::open(&dir).unwrap();
+        assert_eq!(reopened.claim("wh:a".into(), "dlv_3", 150, 250), Err("dlv_1".into()));
+        assert_eq!(reopened.claim("wh:b".into(), "dlv_4", 150, 250), Ok(()));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn changes_are_written_in_the_background() {
+        let dir = temp_dir();
+        let cache = DedupeCache::open(&dir).unwrap();
+        cache.claim("wh:a".into(), "dlv_1", 100, 200).unwrap();
+        tokio::time::sleep(FLUSH_DELAY * 3).await;
+        let content = std::fs::read_to_string(dir.join("dedupe.json")).unwrap();
+        assert!(content.contains("dlv_1"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::rules::lookup;
+use crate::types::{Delivery, Webhook};
+use serde_json::Value;
+
+/// Longest value a single placeholder may contribute to the event type.
+const MAX_VALUE_LEN: usize = 64;
+
+/// A parsed event type template such as
+/// `com.github.{header:x-github-event}.{body:action|unknown}`.
+///
+/// Each `{...}` holds one or more `|`-separated alternatives, tried in order:
+/// - `header:<name>` — a request header (case-insensitive)
+/// - `body:<path>` — a dotted path into the JSON body; numeric segments
+///   index arrays. Strings, numbers and booleans resolve; anything else
+///   doesn't.
+/// - anything else is a literal fallback, which may be empty to drop the
+///   segment altogether.
+pub struct EventTypeTemplate {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    Placeholder(Vec<Source>),
+}
+
+enum Source {
+    Header(String),
+    Body(String),
+    Literal(String),
+}
+
+impl EventTypeTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .map(|i| open + i)
+                .ok_or_else(|| format!("unclosed '{{' in event type template: {template}"))?;
+            let inner = &rest[open + 1..close];
+            if inner.contains('{') {
+                return Err(format!("nested '{{' in event type template: {template}"));
+            }
+            let sources = inner
+                .split('|')
+                .map(parse_source)
+                .collect::<Result<Vec<_>, _>>()?;
+            parts.push(Part::Placeholder(sources));
+            rest = &rest[close + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("unmatched '}}' in event type template: {template}"));
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Render against a delivery's headers and body. Returns `None` if a
+    /// placeholder has no alternative that resolves, or the result is empty.
+    pub fn render(&self, headers: &Value, body: &Value) -> Option<String> {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Placeholder(sources) => {
+                    let value = sources.iter().find_map(|s| s.resolve(headers, body))?;
+                    out.push_str(&sanitize(&value));
+                }
+            }
+        }
+
+        // Empty values and fallbacks leave stray dots behind; collapse them.
+        let rendered = out
+            .split('.')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join(".");
+        (!rendered.is_empty()).then_some(rendered)
+    }
+}
+
+impl Source {
+    fn resolve(&self, headers: &Value, body: &Value) -> Option<String> {
+        match self {
+            Source::Literal(text) => Some(text.clone()),
+            Source::Header(name) => headers
+                .get(name)
+                .and_then(|v| v.as_str())
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string()),
+            Source::Body(path) => match lookup(body, path)? {
+                Value::String(s) if !s.is_empty() => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                Value::Bool(b) => Some(b.to_string()),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn parse_source(alternative: &str) -> Result<Source, String> {
+    let alternative = alternative.trim();
+    match alternative.split_once(':') {
+        Some(("header", name)) if !name.is_empty() => Ok(Source::Header(name.to_ascii_lowercase())),
+        Some(("body", path)) if !path.is_empty() => {
+            Ok(Source::Body(path.to_string()))
+        }
+        Some((source, _)) => Err(format!(
+            "invalid placeholder '{alternative}': expected header:<name> or body:<path>, not '{source}:'"
+        )),
+        None => Ok(Source::Literal(alternative.to_string())),
+    }
+}
+
+/// Keep event types to `[A-Za-z0-9._-]`: anything else becomes `_`, and
+/// each value is capped at `MAX_VALUE_LEN` characters.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .take(MAX_VALUE_LEN)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The event type to publish a delivery under: the webhook's template if it
+/// has one and it renders, otherwise its static `event_type`.
+pub fn event_type_for(wh: &Webhook, delivery: &Delivery) -> String {
+    wh.event_type_template
+        .as_deref()
+        .and_then(|t| EventTypeTemplate::parse(t).ok())
+        .and_then(|t| t.render(&delivery.headers, &delivery.body))
+        .unwrap_or_else(|| wh.event_type.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(template: &str, headers: &Value, body: &Value) -> Option<String> {
+        EventTypeTemplate::parse(template).unwrap().render(headers, body)
+    }
+
+    #[test]
+    fn placeholders_resolve_headers_and_body_paths() {
+        let headers = json!({ "x-github-event": "pull_request" });
+        let body = json!({ "action": "opened", "items": [{ "n": 7 }, { "ok": true }] });
+        assert_eq!(
+            render("com.github.{header:x-github-event}.{body:action}", &headers, &body).as_deref(),
+            Some("com.github.pull_request.opened")
+        );
+        // Header names are case-insensitive; numeric segments index arrays.
+        assert_eq!(
+            render("{header:X-GitHub-Event}.{body:items.0.n}.{body:items.1.ok}", &headers, &body)
+                .as_deref(),
+            Some("pull_request.7.true")
+        );
+    }
+
+    #[test]
+    fn alternatives_fall_back_in_order() {
+        let headers = json!({ "x-empty": "" });
+        let body = json!({ "type": "invoice.paid", "nested": { "obj": {} } });
+        assert_eq!(
+            render("stripe.{body:event|body:type|unknown}", &headers, &body).as_deref(),
+            Some("stripe.invoice.paid")
+        );
+        // Empty strings and non-scalar values don't resolve.
+        assert_eq!(
+            render("x.{header:x-empty|body:nested.obj|other}", &headers, &body).as_deref(),
+            Some("x.other")
+        );
+        // An empty literal drops the segment and its dot.
+        assert_eq!(render("a.{body:missing|}.b", &headers, &body).as_deref(), Some("a.b"));
+        // No alternative resolves.
+        assert_eq!(render("a.{body:missing}", &headers, &body), None);
+        assert_eq!(render("{body:missing|}", &headers, &body), None);
+    }
+
+    #[test]
+    fn values_are_sanitized_and_capped() {
+        let body = json!({ "name": "Hello World/ü!", "long": "x".repeat(100) });
+        assert_eq!(
+            render("t.{body:name}", &json!({}), &body).as_deref(),
+            Some("t.Hello_World___")
+        );
+        let long = render("{body:long}", &json!({}), &body).unwrap();
+        assert_eq!(long.len(), MAX_VALUE_LEN);
+    }
+
+    #[test]
+    fn malformed_templates_are_rejected() {
+        assert!(EventTypeTemplate::parse("a.{body:x").is_err());
+        assert!(EventTypeTemplate::parse("a.{body:{x}}").is_err());
+        assert!(EventTypeTemplate::parse("a.b}").is_err());
+        assert!(EventTypeTemplate::parse("a.{query:x}").is_err());
+        assert!(EventTypeTemplate::parse("a.{header:}").is_err());
+        assert!(EventTypeTemplate::parse("plain.type").is_ok());
+    }
+
+    #[test]
+    fn event_type_falls_back_to_the_static_type() {
+        let mut wh: Webhook = serde_json::from_value(json!({
+            "id": "wh_test",
+            "name": "Test",
+            "event_type": "webhook.received",
+            "event_type_template": "gh.{header:x-github-event}",
+            "verification_method": "none",
+            "status": "active",
+            "url": "http://127.0.0.1/hooks/wh_test",
+            "trigger_count": 0,
+            "last_triggered": null,
+            "created_at": "2026-03-01T00:00:00Z",
+        }))
+        .unwrap();
+        let mut delivery: Delivery = serde_json::from_value(json!({
+            "id": "dlv",
+            "webhook_id": "wh_test",
+            "received_at": "2026-03-01T00:00:00Z",
+            "status": "success",
+            "headers": { "x-github-event": "push" },
+            "body": {},
+        }))
+        .unwrap();
+        assert_eq!(event_type_for(&wh, &delivery), "gh.push");
+        delivery.headers = json!({});
+        assert_eq!(event_type_for(&wh, &delivery), "webhook.received");
+        wh.event_type_template = None;
+        assert_eq!(event_type_for(&wh, &delivery), "webhook.received");
+    }
+}
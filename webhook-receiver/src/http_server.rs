@@ -1,15 +1,31 @@
-use crate::types::{Delivery, PendingEvent, Webhook};
-use crate::verification;
+use crate::body;
+use crate::bridge::PendingReplies;
+use crate::challenge;
+use crate::dedupe::{self, DedupeCache};
+use crate::event_type;
+use crate::jwt::{self, JwksCache};
+use crate::limits::{self, RateLimiter, ServerLimits};
+use crate::outbox::Outbox;
+use crate::rules::{self, Outcome};
+use crate::tls::{self, CertResolver, TlsListener};
+use crate::types::{Delivery, PendingEvent, ServerSettings, Webhook};
+use crate::verification::{self, SeenMessages, VerifyError};
 use axum::{
-    body::Bytes,
-    extract::{Path, State},
-    http::{HeaderMap, StatusCode},
+    body::Body,
+    extract::{ConnectInfo, Path, State},
+    serve::ListenerExt,
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
     routing::post,
     Router,
 };
 use chrono::Utc;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::sync::{mpsc, RwLock};
 
@@ -17,138 +33,788 @@ use tokio::sync::{mpsc, RwLock};
 /// Refreshed whenever webhooks are mutated.
 pub type WebhookMap = Arc<RwLock<HashMap<String, Webhook>>>;
 
+/// The server settings the HTTP server consults per request, replaced when
+/// they change. Never held across an await.
+pub type SharedConfig = Arc<std::sync::RwLock<ServerConfig>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct ServerConfig {
+    pub limits: ServerLimits,
+    /// Header name patterns masked in stored deliveries.
+    pub redacted_headers: Vec<String>,
+}
+
+impl ServerConfig {
+    pub fn from_settings(settings: &ServerSettings) -> Result<Self, String> {
+        Ok(Self {
+            limits: ServerLimits::from_settings(settings)?,
+            redacted_headers: redacted_header_patterns(settings),
+        })
+    }
+}
+
+/// How many Standard Webhooks message ids to remember for replay protection.
+const SEEN_MESSAGE_CAPACITY: usize = 10_000;
+
+/// The header patterns in effect, defaults included.
+pub fn redacted_header_patterns(settings: &ServerSettings) -> Vec<String> {
+    settings
+        .redacted_headers
+        .clone()
+        .unwrap_or_else(|| DEFAULT_REDACTED_HEADERS.iter().map(|h| h.to_string()).collect())
+}
+
+/// Headers masked in stored deliveries unless the settings say otherwise.
+/// A leading or trailing `*` matches any prefix or suffix.
+pub const DEFAULT_REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-hub-signature",
+    "x-hub-signature-256",
+    "webhook-signature",
+    "stripe-signature",
+    "x-slack-signature",
+    "x-shopify-hmac-sha256",
+    "x-gitlab-token",
+    "x-twilio-signature",
+    "linear-signature",
+    "*token*",
+];
+
 #[derive(Clone)]
 struct ServerState {
     webhooks: WebhookMap,
-    event_tx: mpsc::UnboundedSender<(PendingEvent, Delivery)>,
+    config: SharedConfig,
+    rate_limiter: Arc<RateLimiter>,
+    outbox: Arc<Outbox>,
+    dedupe: Arc<DedupeCache>,
+    replies: Arc<PendingReplies>,
+    rejections: mpsc::Sender<Delivery>,
+    seen_messages: Arc<std::sync::Mutex<SeenMessages>>,
+    jwks: Arc<JwksCache>,
 }
 
-/// Spawn the Axum server on 127.0.0.1:0 and return the bound port and
-/// a channel receiver for incoming webhook deliveries.
+/// Spawn the Axum server on `bind_address`, preferring `port`, and return
+/// the bound address. If the preferred port is taken, an ephemeral port is
+/// used instead. With `tls`, connections are served over HTTPS using the
+/// resolver's (hot-reloaded) certificate. Accepted deliveries are written
+/// to the outbox before the 200 goes out; rejected, filtered and duplicate
+/// requests and answered handshakes are reported on `rejections` for
+/// recording. Requests are checked against the server-wide limits in
+/// `config` and each webhook's own limits before their body is read. Requests to sync-reply webhooks wait
+/// on `replies` for the host's answer.
+#[allow(clippy::too_many_arguments)]
 pub async fn start(
+    bind_address: &str,
+    port: Option<u16>,
+    tls: Option<Arc<CertResolver>>,
     webhooks: WebhookMap,
-) -> Result<(u16, mpsc::UnboundedReceiver<(PendingEvent, Delivery)>), String> {
-    let (event_tx, event_rx) = mpsc::unbounded_channel();
+    config: SharedConfig,
+    outbox: Arc<Outbox>,
+    dedupe: Arc<DedupeCache>,
+    replies: Arc<PendingReplies>,
+    rejections: mpsc::Sender<Delivery>,
+) -> Result<SocketAddr, String> {
+    let ip: IpAddr = bind_address
+        .parse()
+        .map_err(|e| format!("invalid bind address '{bind_address}': {e}"))?;
 
     let state = ServerState {
         webhooks,
-        event_tx,
+        config,
+        rate_limiter: Arc::new(RateLimiter::default()),
+        outbox,
+        dedupe,
+        replies,
+        rejections,
+        seen_messages: Arc::new(std::sync::Mutex::new(SeenMessages::new(SEEN_MESSAGE_CAPACITY))),
+        jwks: Arc::new(JwksCache::default()),
     };
 
     let app = Router::new()
-        .route("/hooks/{webhook_id}", post(handle_webhook))
+        // GET only serves handshakes (Meta, Dropbox).
+        .route("/hooks/{webhook_id}", post(handle_webhook).get(handle_webhook))
         .with_state(state);
 
-    let listener = TcpListener::bind("127.0.0.1:0")
-        .await
-        .map_err(|e| format!("failed to bind HTTP server: {e}"))?;
+    let preferred = SocketAddr::new(ip, port.unwrap_or(0));
+    let listener = match TcpListener::bind(preferred).await {
+        Ok(l) => l,
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && preferred.port() != 0 => {
+            eprintln!(
+                "webhook-receiver: port {} is in use, falling back to an ephemeral port",
+                preferred.port()
+            );
+            TcpListener::bind(SocketAddr::new(ip, 0))
+                .await
+                .map_err(|e| format!("failed to bind HTTP server: {e}"))?
+        }
+        Err(e) => return Err(format!("failed to bind HTTP server on {preferred}: {e}")),
+    };
 
-    let port = listener.local_addr().unwrap().port();
-    eprintln!("webhook-receiver: HTTP server bound on port {port}");
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    eprintln!("webhook-receiver: HTTP server bound on {addr}");
 
-    tokio::spawn(async move {
-        axum::serve(listener, app).await.unwrap_or_else(|e| {
-            eprintln!("webhook-receiver: HTTP server error: {e}");
-        });
-    });
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    match tls {
+        Some(resolver) => {
+            let acceptor = tls::acceptor(resolver.clone())?;
+            // tap_io is a no-op here; it's what lets axum provide
+            // ConnectInfo<SocketAddr> for a custom listener.
+            let listener = TlsListener::new(listener, acceptor)?.tap_io(|_| {});
+            tokio::spawn(resolver.watch());
+            tokio::spawn(async move {
+                axum::serve(listener, service).await.unwrap_or_else(|e| {
+                    eprintln!("webhook-receiver: HTTPS server error: {e}");
+                });
+            });
+        }
+        None => {
+            tokio::spawn(async move {
+                axum::serve(listener, service).await.unwrap_or_else(|e| {
+                    eprintln!("webhook-receiver: HTTP server error: {e}");
+                });
+            });
+        }
+    }
 
-    Ok((port, event_rx))
+    Ok(addr)
+}
+
+/// Build the CloudEvent to publish for a delivery. Shared by live requests
+/// and replays so both produce the same event for the same payload.
+/// Returns the reason if the webhook's rules drop the delivery.
+pub fn event_for(wh: &Webhook, delivery: &Delivery) -> Result<PendingEvent, String> {
+    let data = match rules::apply(&wh.rules, &delivery.headers, &delivery.body) {
+        Outcome::Publish(data) => data,
+        Outcome::Drop(reason) => return Err(reason),
+    };
+    Ok(PendingEvent {
+        event_type: event_type::event_type_for(wh, delivery),
+        subject: wh.id.clone(),
+        data,
+        replayed_from: delivery.replayed_from.clone(),
+        correlation_id: None,
+    })
 }
 
 async fn handle_webhook(
     Path(webhook_id): Path<String>,
     State(state): State<ServerState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    method: Method,
+    uri: Uri,
     headers: HeaderMap,
-    body: Bytes,
-) -> StatusCode {
-    let webhooks = state.webhooks.read().await;
-    let wh = match webhooks.get(&webhook_id) {
-        Some(w) => w.clone(),
-        None => return StatusCode::NOT_FOUND,
+    body: Body,
+) -> Response {
+    let server_limits = state.config.read().unwrap().limits.clone();
+    let client = limits::client_ip(remote.ip(), &headers, &server_limits.trusted_proxies);
+    // Record the forwarded client when there is one, otherwise the socket.
+    let source = if client == remote.ip() {
+        remote.to_string()
+    } else {
+        client.to_string()
     };
+
+    let webhooks = state.webhooks.read().await;
+    let wh = webhooks.get(&webhook_id).cloned();
     drop(webhooks);
 
+    // Server-wide limits first, so unknown ids are limited too; then the
+    // webhook's own. Nothing is read from the body until both pass.
+    let scopes = std::iter::once(("_global", &server_limits.limits))
+        .chain(wh.as_ref().map(|w| (w.id.as_str(), &w.limits)));
+    for (scope, limits) in scopes {
+        if let Err(reason) = limits.check_ip(client) {
+            reject(&state, &webhook_id, "rejected", reason, &headers, None, &source);
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        if let Some(rate_limit) = &limits.rate_limit {
+            if let Err(wait) = state.rate_limiter.take(scope, rate_limit) {
+                reject(&state, &webhook_id, "rejected", "rate_limited", &headers, None, &source);
+                let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                )
+                    .into_response();
+            }
+        }
+    }
+
+    let max_body = std::iter::once(&server_limits.limits)
+        .chain(wh.as_ref().map(|w| &w.limits))
+        .filter_map(|l| l.max_body_bytes)
+        .min()
+        .unwrap_or(limits::DEFAULT_MAX_BODY_BYTES);
+    let body = match Limited::new(body, max_body as usize).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) if e.is::<LengthLimitError>() => {
+            reject(&state, &webhook_id, "rejected", "body_too_large", &headers, None, &source);
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        }
+        // The client went away mid-body; there's nobody to answer.
+        Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+    };
+
+    let wh = match wh {
+        Some(w) => w,
+        None => {
+            reject(&state, &webhook_id, "not_found", "not_found", &headers, Some(&body), &source);
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+
     if wh.status == "paused" {
-        return StatusCode::SERVICE_UNAVAILABLE;
+        reject(&state, &webhook_id, "rejected", "paused", &headers, Some(&body), &source);
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
     }
 
-    // Verify signature
-    let verified = match wh.verification_method.as_str() {
-        "none" => true,
+    // Endpoint checks carry no signature, so answer them before verifying.
+    match challenge::unsigned(&wh, &method, uri.query()) {
+        Some(Ok(handshake)) => {
+            reject(&state, &webhook_id, "handshake", handshake.kind, &headers, Some(&body), &source);
+            return handshake.into_response();
+        }
+        Some(Err(reason)) => {
+            reject(&state, &webhook_id, "verification_failed", reason, &headers, Some(&body), &source);
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        None if method != Method::POST => return StatusCode::METHOD_NOT_ALLOWED.into_response(),
+        None => {}
+    }
+
+    let verified = match verify_request(&state, &wh, &uri, &headers, &body) {
+        Ok(verified) => verified,
+        Err(e) => {
+            eprintln!("webhook-receiver: verification failed for webhook {webhook_id}: {e}");
+            reject(&state, &webhook_id, "verification_failed", e.as_str(), &headers, Some(&body), &source);
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    };
+
+    let body_value = body::decode(&headers, &body, max_body);
+
+    if let Some(handshake) = challenge::signed(&wh, &body_value) {
+        reject(&state, &webhook_id, "handshake", handshake.kind, &headers, Some(&body), &source);
+        return handshake.into_response();
+    }
+
+    let delivery_id = uuid::Uuid::new_v4().to_string();
+    let delivery = Delivery {
+        id: delivery_id.clone(),
+        webhook_id: webhook_id.clone(),
+        received_at: Utc::now(),
+        status: "success".to_string(),
+        headers: header_json(&headers),
+        body: body_value,
+        query: body::query_params(uri.query()),
+        sequence: None,
+        replayed_from: None,
+        reason: None,
+        body_sha256: Some(body_sha256(&body)),
+        source_addr: Some(source),
+        secret_id: verified.secret_id,
+        claims: verified.claims,
+    };
+
+    // Filtered and duplicate deliveries get the same answer as accepted
+    // ones, so rendered up front.
+    let accepted = match &wh.response {
+        Some(template) => template.render(&delivery),
+        None => StatusCode::OK.into_response(),
+    };
+
+    // A retry of something already accepted: acknowledge it so the sender
+    // stops, but don't publish it again.
+    let mut claims = RetryClaims {
+        state: &state,
+        dedupe_key: None,
+        message_id: None,
+    };
+    let now = Utc::now().timestamp();
+    if let Some(key) = dedupe::key_for(&wh, &delivery) {
+        let window = wh.dedupe_window_seconds.unwrap_or(dedupe::DEFAULT_WINDOW_SECS);
+        if let Err(original) = state.dedupe.claim(key.clone(), &delivery_id, now, now + window as i64) {
+            skip(&state, delivery, "duplicate", format!("duplicate of {original}"));
+            return accepted;
+        }
+        claims.dedupe_key = Some(key);
+    }
+    // Standard Webhooks senders retry with the same message id. Ids are
+    // only remembered once the signature checks out, so forged requests
+    // can't burn them ahead of the real sender.
+    if wh.verification_method == "standard-webhooks" {
+        let msg_id = headers.get("webhook-id").and_then(|v| v.to_str().ok()).unwrap_or_default();
+        let key = format!("{}:{msg_id}", wh.id);
+        let tolerance = wh.timestamp_tolerance_seconds.unwrap_or(verification::DEFAULT_TOLERANCE_SECS);
+        if !state.seen_messages.lock().unwrap().insert(key.clone(), now, now + tolerance as i64) {
+            skip(&state, delivery, "duplicate", format!("duplicate of message {msg_id}"));
+            return accepted;
+        }
+        claims.message_id = Some(key);
+    }
+
+    let mut event = match event_for(&wh, &delivery) {
+        Ok(event) => event,
+        Err(reason) => {
+            // Authentic but unwanted: answer 200 so the sender doesn't retry.
+            claims.keep();
+            skip(&state, delivery, "filtered", reason);
+            return accepted;
+        }
+    };
+
+    let waiter = wh.sync_reply.as_ref().map(|sync| {
+        event.correlation_id = Some(delivery_id.clone());
+        (state.replies.register(&delivery_id), sync.timeout_ms)
+    });
+
+    // Rules and templates have seen the real headers; what's kept is masked.
+    let delivery = Delivery {
+        headers: redact_headers(&state, &delivery.headers),
+        ..delivery
+    };
+
+    // Only acknowledge once the delivery is on disk; a 500 makes the sender
+    // retry, and dropping the claims keeps that retry from looking like a
+    // duplicate.
+    if let Err(e) = state.outbox.enqueue(event, delivery).await {
+        eprintln!("webhook-receiver: failed to enqueue delivery for {webhook_id}: {e}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    claims.keep();
+
+    if let Some((waiter, timeout_ms)) = waiter {
+        return match waiter.reply(Duration::from_millis(timeout_ms)).await {
+            Some(reply) => reply.into_response(),
+            None if wh.response.is_some() => accepted,
+            None => {
+                eprintln!("webhook-receiver: no reply for delivery {delivery_id} within {timeout_ms}ms");
+                StatusCode::GATEWAY_TIMEOUT.into_response()
+            }
+        };
+    }
+
+    accepted
+}
+
+/// What a delivery holds against retries until it is in the outbox: its
+/// dedupe key and its Standard Webhooks message id. Dropped without `keep`,
+/// because the enqueue failed or the request went away first, both are
+/// released so the sender's retry is accepted.
+struct RetryClaims<'a> {
+    state: &'a ServerState,
+    dedupe_key: Option<String>,
+    message_id: Option<String>,
+}
+
+impl RetryClaims<'_> {
+    fn keep(&mut self) {
+        self.dedupe_key = None;
+        self.message_id = None;
+    }
+}
+
+impl Drop for RetryClaims<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = &self.dedupe_key {
+            self.state.dedupe.release(key);
+        }
+        if let Some(key) = &self.message_id {
+            self.state.seen_messages.lock().unwrap().remove(key);
+        }
+    }
+}
+
+/// What verification established about a request.
+#[derive(Default)]
+struct Verified {
+    secret_id: Option<String>,
+    claims: Option<serde_json::Value>,
+}
+
+/// Check a request against the webhook's verification method, trying each
+/// active secret in turn.
+fn verify_request(
+    state: &ServerState,
+    wh: &Webhook,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Verified, VerifyError> {
+    match wh.verification_method.as_str() {
+        "none" => return Ok(Verified::default()),
+        "jwt" => return verify_jwt(state, wh, headers),
+        _ => {}
+    }
+    let now = Utc::now();
+    let mut result = Err(VerifyError::MissingSecret);
+    for secret in wh.secrets.iter().filter(|s| s.is_active(now)) {
+        result = verify_with_secret(wh, &secret.value, uri, headers, body);
+        if result.is_ok() {
+            return Ok(Verified {
+                secret_id: Some(secret.id.clone()),
+                claims: None,
+            });
+        }
+    }
+    result.map(|()| Verified::default())
+}
+
+/// Verify `Authorization: Bearer <jwt>`: HS256 against the active secrets,
+/// RS256 and ES256 against the configured JWKS, then the claims.
+fn verify_jwt(state: &ServerState, wh: &Webhook, headers: &HeaderMap) -> Result<Verified, VerifyError> {
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(VerifyError::MissingHeader)?;
+    let token = verification::credentials(authorization, "Bearer").ok_or(VerifyError::MalformedToken)?;
+    let token = jwt::Token::decode(token)?;
+    let config = wh.jwt.clone().unwrap_or_default();
+    if !config.algorithms.is_empty() && !config.algorithms.contains(&token.alg) {
+        return Err(VerifyError::UnknownKey);
+    }
+
+    let mut secret_id = None;
+    match token.alg.as_str() {
+        "HS256" => {
+            let now = Utc::now();
+            let mut result = Err(VerifyError::MissingSecret);
+            for secret in wh.secrets.iter().filter(|s| s.is_active(now)) {
+                result = token.verify_hmac(&secret.value);
+                if result.is_ok() {
+                    secret_id = Some(secret.id.clone());
+                    break;
+                }
+            }
+            result?;
+        }
+        "RS256" | "ES256" => {
+            let path = config.jwks_path.as_deref().ok_or(VerifyError::UnknownKey)?;
+            let jwks = state.jwks.get(path).map_err(|e| {
+                eprintln!("webhook-receiver: JWKS unavailable for webhook {}: {e}", wh.id);
+                VerifyError::UnknownKey
+            })?;
+            token.verify_jwks(&jwks)?;
+        }
+        _ => return Err(VerifyError::UnknownKey),
+    }
+
+    let claims = token.claims(&config, Utc::now().timestamp())?;
+    Ok(Verified {
+        secret_id,
+        claims: Some(claims),
+    })
+}
+
+fn verify_with_secret(
+    wh: &Webhook,
+    secret: &str,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), VerifyError> {
+    let header = |name: &str| -> Result<&str, VerifyError> {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+            .ok_or(VerifyError::MissingHeader)
+    };
+    let secret = || -> Result<&str, VerifyError> {
+        Some(secret).filter(|s| !s.is_empty()).ok_or(VerifyError::MissingSecret)
+    };
+    let tolerance = |default: u64| wh.timestamp_tolerance_seconds.unwrap_or(default);
+
+    match wh.verification_method.as_str() {
+        "none" => Ok(()),
         "github-hmac" => {
-            let secret = wh.verification_secret.as_deref().unwrap_or("");
-            let sig = headers
-                .get("x-hub-signature-256")
-                .and_then(|v| v.to_str().ok())
-                .unwrap_or("");
-            verification::verify_github_hmac(secret, &body, sig)
+            let secret = secret()?;
+            let sig = header("x-hub-signature-256")?;
+            verification::verify_github_hmac(secret, body, sig)
         }
         "standard-webhooks" => {
-            let secret = wh.verification_secret.as_deref().unwrap_or("");
-            let msg_id = headers
-                .get("webhook-id")
-                .and_then(|v| v.to_str().ok())
-                .unwrap_or("");
-            let timestamp = headers
-                .get("webhook-timestamp")
-                .and_then(|v| v.to_str().ok())
-                .unwrap_or("");
-            let sig = headers
-                .get("webhook-signature")
-                .and_then(|v| v.to_str().ok())
-                .unwrap_or("");
-            verification::verify_standard_webhooks(secret, &body, msg_id, timestamp, sig)
+            let secret = secret()?;
+            let msg_id = header("webhook-id")?;
+            let timestamp = header("webhook-timestamp")?;
+            let sig = header("webhook-signature")?;
+            let tolerance = tolerance(verification::DEFAULT_TOLERANCE_SECS);
+            let now = Utc::now().timestamp();
+            verification::verify_standard_webhooks(
+                secret, body, msg_id, timestamp, sig, tolerance, now,
+            )
         }
         "custom-header" => {
             // Secret format: "Header-Name:expected-value"
-            let secret = wh.verification_secret.as_deref().unwrap_or("");
-            if let Some((header_name, expected)) = secret.split_once(':') {
-                let actual = headers
-                    .get(header_name)
-                    .and_then(|v| v.to_str().ok())
-                    .unwrap_or("");
-                verification::verify_custom_header(expected, actual)
+            let (header_name, expected) = secret()?
+                .split_once(':')
+                .ok_or(VerifyError::MalformedSecret)?;
+            let actual = header(header_name)?;
+            verification::verify_custom_header(expected, actual)
+        }
+        "stripe" => verification::verify_stripe(
+            secret()?,
+            body,
+            header("stripe-signature")?,
+            tolerance(verification::DEFAULT_TOLERANCE_SECS),
+            Utc::now().timestamp(),
+        ),
+        "slack" => verification::verify_slack(
+            secret()?,
+            body,
+            header("x-slack-request-timestamp")?,
+            header("x-slack-signature")?,
+            tolerance(verification::DEFAULT_TOLERANCE_SECS),
+            Utc::now().timestamp(),
+        ),
+        "shopify" => {
+            verification::verify_shopify(secret()?, body, header("x-shopify-hmac-sha256")?)
+        }
+        "gitlab" => verification::verify_custom_header(secret()?, header("x-gitlab-token")?),
+        "bearer" => verification::verify_bearer(secret()?, header("authorization")?),
+        "basic" => verification::verify_basic(secret()?, header("authorization")?),
+        "twilio" => {
+            let secret = secret()?;
+            let sig = header("x-twilio-signature")?;
+            // Twilio signs the URL it was configured with, which is the
+            // webhook's URL plus whatever query string it appended.
+            let url = match uri.query() {
+                Some(query) => format!("{}?{query}", wh.url),
+                None => wh.url.clone(),
+            };
+            let is_form = headers
+                .get("content-type")
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|ct| ct.starts_with("application/x-www-form-urlencoded"));
+            let params: Vec<(String, String)> = if is_form {
+                form_urlencoded::parse(body).into_owned().collect()
             } else {
-                false
-            }
+                Vec::new()
+            };
+            verification::verify_twilio(secret, &url, &params, body, sig)
         }
-        _ => false,
-    };
+        "linear" => verification::verify_linear(
+            secret()?,
+            body,
+            header("linear-signature")?,
+            tolerance(verification::LINEAR_TOLERANCE_SECS),
+            Utc::now().timestamp(),
+        ),
+        _ => Err(VerifyError::UnknownMethod),
+    }
+}
 
-    if !verified {
-        eprintln!("webhook-receiver: verification failed for webhook {webhook_id}");
-        return StatusCode::UNAUTHORIZED;
+/// Report a verified delivery that won't be published to the recorder.
+fn skip(state: &ServerState, delivery: Delivery, status: &str, reason: String) {
+    let webhook_id = delivery.webhook_id.clone();
+    let skipped = Delivery {
+        status: status.to_string(),
+        reason: Some(reason),
+        headers: redact_headers(state, &delivery.headers),
+        ..delivery
+    };
+    if state.rejections.try_send(skipped).is_err() {
+        eprintln!("webhook-receiver: rejection log full, dropping record for {webhook_id}");
     }
+}
 
-    // Parse body as JSON; fall back to raw string
-    let body_value: serde_json::Value = serde_json::from_slice(&body)
-        .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(&body).into()));
+/// Report a rejected request, or an answered handshake, to the recorder.
+/// The body itself is not kept, only its hash (none if it was refused
+/// unread), and credential-bearing headers are masked.
+fn reject(
+    state: &ServerState,
+    webhook_id: &str,
+    status: &str,
+    reason: &str,
+    headers: &HeaderMap,
+    body: Option<&[u8]>,
+    source: &str,
+) {
+    let delivery = Delivery {
+        id: uuid::Uuid::new_v4().to_string(),
+        webhook_id: webhook_id.to_string(),
+        received_at: Utc::now(),
+        status: status.to_string(),
+        headers: redact_headers(state, &header_json(headers)),
+        body: serde_json::Value::Null,
+        query: None,
+        sequence: None,
+        replayed_from: None,
+        reason: Some(reason.to_string()),
+        body_sha256: body.map(body_sha256),
+        source_addr: Some(source.to_string()),
+        secret_id: None,
+        claims: None,
+    };
+    // Drop rather than queue without bound if we're being flooded.
+    if state.rejections.try_send(delivery).is_err() {
+        eprintln!("webhook-receiver: rejection log full, dropping record for {webhook_id}");
+    }
+}
 
-    // Capture request headers as a JSON object
-    let header_map: serde_json::Map<String, serde_json::Value> = headers
+/// Capture request headers as a JSON object, skipping non-UTF-8 values.
+fn header_json(headers: &HeaderMap) -> serde_json::Value {
+    let map: serde_json::Map<String, serde_json::Value> = headers
         .iter()
         .filter_map(|(k, v)| {
             v.to_str().ok().map(|s| (k.to_string(), serde_json::Value::String(s.to_string())))
         })
         .collect();
+    serde_json::Value::Object(map)
+}
 
-    let delivery_id = uuid::Uuid::new_v4().to_string();
-    let delivery = Delivery {
-        id: delivery_id.clone(),
-        webhook_id: webhook_id.clone(),
-        received_at: Utc::now(),
-        status: "success".to_string(),
-        headers: serde_json::Value::Object(header_map),
-        body: body_value.clone(),
-    };
+/// Mask the values of headers matching the configured patterns.
+fn redact_headers(state: &ServerState, headers: &serde_json::Value) -> serde_json::Value {
+    let config = state.config.read().unwrap();
+    let mut headers = headers.clone();
+    if let Some(map) = headers.as_object_mut() {
+        for (name, value) in map.iter_mut() {
+            if config.redacted_headers.iter().any(|p| header_matches(p, name)) {
+                *value = serde_json::Value::String("[redacted]".into());
+            }
+        }
+    }
+    headers
+}
 
-    let event = PendingEvent {
-        event_type: wh.event_type.clone(),
-        subject: webhook_id.clone(),
-        data: body_value,
-    };
+/// Case-insensitive match with an optional `*` at either end.
+fn header_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let name = name.to_ascii_lowercase();
+    match (pattern.strip_prefix('*'), pattern.strip_suffix('*')) {
+        (Some(_), Some(_)) => name.contains(pattern.trim_matches('*')),
+        (Some(suffix), None) => name.ends_with(suffix),
+        (None, Some(prefix)) => name.starts_with(prefix),
+        _ => name == pattern,
+    }
+}
+
+fn body_sha256(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
 
-    let _ = state.event_tx.send((event, delivery));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selftest;
+    use serde_json::json;
 
-    StatusCode::OK
+    #[test]
+    fn header_patterns() {
+        assert!(header_matches("Authorization", "authorization"));
+        assert!(header_matches("*token*", "X-Gitlab-Token"));
+        assert!(header_matches("x-hub-*", "x-hub-signature-256"));
+        assert!(header_matches("*-signature", "stripe-signature"));
+        assert!(header_matches("*", "anything"));
+        assert!(!header_matches("cookie", "set-cookie"));
+        assert!(!header_matches("x-hub-*", "x-github-event"));
+    }
+
+    fn webhook(method: &str, secret: &str) -> Webhook {
+        serde_json::from_value(json!({
+            "id": "wh_test",
+            "name": "test",
+            "event_type": "com.example.test",
+            "verification_method": method,
+            "secrets": [crate::secrets::WebhookSecret::new(secret.into())],
+            "status": "active",
+            "url": "http://127.0.0.1/hooks/wh_test",
+            "trigger_count": 0,
+            "last_triggered": null,
+            "created_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn server_state(dir: &std::path::Path, wh: Webhook) -> (ServerState, mpsc::Receiver<Delivery>) {
+        let (rejections, rx) = mpsc::channel(16);
+        let state = ServerState {
+            webhooks: Arc::new(RwLock::new(HashMap::from([(wh.id.clone(), wh)]))),
+            config: Default::default(),
+            rate_limiter: Default::default(),
+            outbox: Outbox::open(dir).unwrap(),
+            dedupe: DedupeCache::open(dir).unwrap(),
+            replies: Default::default(),
+            rejections,
+            seen_messages: Arc::new(std::sync::Mutex::new(SeenMessages::new(16))),
+            jwks: Default::default(),
+        };
+        (state, rx)
+    }
+
+    async fn post(state: &ServerState, headers: &[(String, String)], body: &[u8]) -> StatusCode {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(
+                header::HeaderName::try_from(name.as_str()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        handle_webhook(
+            Path("wh_test".to_string()),
+            State(state.clone()),
+            ConnectInfo("127.0.0.1:40000".parse().unwrap()),
+            Method::POST,
+            "/hooks/wh_test".parse().unwrap(),
+            map,
+            Body::from(body.to_vec()),
+        )
+        .await
+        .status()
+    }
+
+    // Standard Webhooks retries reuse the message id: one after a failed
+    // enqueue must be accepted, one after a success acknowledged unpublished.
+    #[tokio::test]
+    async fn retry_after_failed_enqueue_is_accepted() {
+        let dir = std::env::temp_dir().join(format!("http-test-{}", uuid::Uuid::new_v4()));
+        for dedupe_key in [None, Some("header:webhook-id")] {
+            let _ = std::fs::remove_dir_all(&dir);
+            let mut wh = webhook("standard-webhooks", "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw");
+            wh.dedupe_key = dedupe_key.map(String::from);
+            let body = br#"{"ok":true}"#;
+            let mut headers = selftest::sign(&wh, body, "application/json", Utc::now()).headers;
+            headers.push(("content-type".into(), "application/json".into()));
+            let (state, mut rejections) = server_state(&dir, wh);
+
+            std::fs::remove_dir_all(dir.join("outbox")).unwrap();
+            assert_eq!(post(&state, &headers, body).await, StatusCode::INTERNAL_SERVER_ERROR);
+            std::fs::create_dir_all(dir.join("outbox")).unwrap();
+            assert_eq!(post(&state, &headers, body).await, StatusCode::OK);
+            let entry = state.outbox.front().await.expect("retry enqueued");
+
+            assert_eq!(post(&state, &headers, body).await, StatusCode::OK);
+            let duplicate = rejections.recv().await.unwrap();
+            assert_eq!(duplicate.status, "duplicate");
+            state.outbox.ack(entry.id).await;
+            assert!(state.outbox.front().await.is_none());
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn jwt_algorithms_are_restricted() {
+        let dir = std::env::temp_dir().join(format!("http-test-{}", uuid::Uuid::new_v4()));
+        let mut wh = webhook("jwt", "hs-secret");
+        let signed = selftest::sign(&wh, b"{}", "application/json", Utc::now());
+        let mut headers = HeaderMap::new();
+        for (name, value) in &signed.headers {
+            headers.insert(header::HeaderName::try_from(name.as_str()).unwrap(), value.parse().unwrap());
+        }
+        let (state, _rejections) = server_state(&dir, wh.clone());
+
+        assert!(verify_jwt(&state, &wh, &headers).is_ok());
+        wh.jwt = Some(jwt::JwtConfig {
+            algorithms: vec!["HS256".into(), "RS256".into()],
+            ..Default::default()
+        });
+        assert!(verify_jwt(&state, &wh, &headers).is_ok());
+        wh.jwt = Some(jwt::JwtConfig {
+            algorithms: vec!["RS256".into()],
+            ..Default::default()
+        });
+        assert_eq!(verify_jwt(&state, &wh, &headers).err(), Some(VerifyError::UnknownKey));
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
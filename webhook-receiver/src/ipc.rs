@@ -0,0 +1,230 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// How long to wait for the host to answer an outbound call.
+const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Outbound side of the stdio JSON-RPC channel.
+///
+/// All writes go through a channel to a single thread that owns stdout, so
+/// responses and outbound calls never interleave mid-line. Outbound calls are
+/// parked in a pending table until the stdin reader hands back the host's
+/// reply with the matching id.
+pub struct Ipc {
+    out: mpsc::UnboundedSender<String>,
+    pending: Mutex<HashMap<u64, oneshot::Sender<Result<Value, String>>>>,
+    next_id: AtomicU64,
+    call_timeout: Duration,
+    /// Set once the host has closed stdin; no reply can arrive after that.
+    closed: AtomicBool,
+}
+
+impl Ipc {
+    pub fn new() -> Arc<Self> {
+        let (out, mut rx) = mpsc::unbounded_channel::<String>();
+        std::thread::spawn(move || {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            while let Some(line) = rx.blocking_recv() {
+                let _ = writeln!(out, "{line}");
+                let _ = out.flush();
+            }
+        });
+        Self::with_output(out, CALL_TIMEOUT)
+    }
+
+    /// An `Ipc` that writes its lines to `out` instead of stdout.
+    pub fn with_output(out: mpsc::UnboundedSender<String>, call_timeout: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            out,
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(20000),
+            call_timeout,
+            closed: AtomicBool::new(false),
+        })
+    }
+
+    /// Write a single message to stdout.
+    pub fn send<T: Serialize>(&self, msg: &T) {
+        let line = serde_json::to_string(msg).expect("serialize message");
+        let _ = self.out.send(line);
+    }
+
+    /// Call a host method and wait for its reply.
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        if self.closed.load(Ordering::SeqCst) {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(format!("{method}: host has closed stdin"));
+        }
+
+        self.send(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": id,
+        }));
+
+        match tokio::time::timeout(self.call_timeout, rx).await {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(_)) => Err(format!("{method}: reply channel closed")),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(format!("{method}: no reply from host within {:?}", self.call_timeout))
+            }
+        }
+    }
+
+    /// Read the host's messages from `input` until EOF. Replies are routed
+    /// to the calls waiting on them; anything else, or the parse error for a
+    /// line that isn't JSON, goes to `request`, which returns false to stop.
+    /// Calls still waiting when input ends are failed rather than left to
+    /// time out.
+    pub fn demultiplex(
+        &self,
+        input: impl BufRead,
+        mut request: impl FnMut(Result<Value, String>) -> bool,
+    ) {
+        for line in input.lines() {
+            let Ok(line) = line else { break };
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let msg = match serde_json::from_str::<Value>(trimmed) {
+                Ok(msg) if msg.get("method").is_none() => {
+                    if !self.resolve(&msg) {
+                        eprintln!("webhook-receiver: dropping unmatched reply: {trimmed}");
+                    }
+                    continue;
+                }
+                Ok(msg) => Ok(msg),
+                Err(e) => Err(format!("Parse error: {e}")),
+            };
+            if !request(msg) {
+                break;
+            }
+        }
+        self.close();
+    }
+
+    /// Fail every outstanding call, and any made later: with stdin closed no
+    /// reply can arrive.
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        for (_, tx) in self.pending.lock().unwrap().drain() {
+            let _ = tx.send(Err("host has closed stdin".to_string()));
+        }
+    }
+
+    /// Route a reply from the host to the call waiting on it. Returns false
+    /// if no call with that id is outstanding (e.g. it already timed out).
+    pub fn resolve(&self, reply: &Value) -> bool {
+        let Some(id) = reply.get("id").and_then(|v| v.as_u64()) else {
+            return false;
+        };
+        let Some(tx) = self.pending.lock().unwrap().remove(&id) else {
+            return false;
+        };
+        let result = match reply.get("error") {
+            Some(err) if !err.is_null() => Err(err
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("unknown error")
+                .to_string()),
+            _ => Ok(reply.get("result").cloned().unwrap_or(Value::Null)),
+        };
+        let _ = tx.send(result);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_ipc(call_timeout: Duration) -> (Arc<Ipc>, mpsc::UnboundedReceiver<String>) {
+        let (out, rx) = mpsc::unbounded_channel();
+        (Ipc::with_output(out, call_timeout), rx)
+    }
+
+    /// Start a call and return it with the id it went out with.
+    async fn start_call(
+        ipc: &Arc<Ipc>,
+        out: &mut mpsc::UnboundedReceiver<String>,
+    ) -> (tokio::task::JoinHandle<Result<Value, String>>, u64) {
+        let caller = ipc.clone();
+        let call = tokio::spawn(async move { caller.call("event.publish", json!({})).await });
+        let sent: Value = serde_json::from_str(&out.recv().await.unwrap()).unwrap();
+        (call, sent["id"].as_u64().unwrap())
+    }
+
+    #[tokio::test]
+    async fn replies_reach_their_callers_out_of_order() {
+        let (ipc, mut out) = test_ipc(CALL_TIMEOUT);
+        let (first, first_id) = start_call(&ipc, &mut out).await;
+        let (second, second_id) = start_call(&ipc, &mut out).await;
+
+        assert!(ipc.resolve(&json!({ "id": second_id, "result": { "n": 2 } })));
+        assert!(ipc.resolve(&json!({ "id": first_id, "error": { "message": "rejected" } })));
+        assert_eq!(second.await.unwrap(), Ok(json!({ "n": 2 })));
+        assert_eq!(first.await.unwrap(), Err("rejected".to_string()));
+
+        // Unknown or missing ids, and ones already answered.
+        assert!(!ipc.resolve(&json!({ "id": first_id, "result": null })));
+        assert!(!ipc.resolve(&json!({ "id": 1, "result": null })));
+        assert!(!ipc.resolve(&json!({ "result": null })));
+    }
+
+    #[tokio::test]
+    async fn timed_out_call_leaves_the_table() {
+        let (ipc, mut out) = test_ipc(Duration::from_millis(20));
+        let (call, id) = start_call(&ipc, &mut out).await;
+        assert!(call.await.unwrap().unwrap_err().contains("no reply from host"));
+        assert!(ipc.pending.lock().unwrap().is_empty());
+        assert!(!ipc.resolve(&json!({ "id": id, "result": null })));
+    }
+
+    #[tokio::test]
+    async fn stdin_is_split_into_requests_and_replies() {
+        let (ipc, mut out) = test_ipc(CALL_TIMEOUT);
+        let (answered, id) = start_call(&ipc, &mut out).await;
+        let (abandoned, _) = start_call(&ipc, &mut out).await;
+
+        let input = format!(
+            "{}\n\n{}\nnot json\n{}\n",
+            json!({ "jsonrpc": "2.0", "method": "execute", "params": {}, "id": 1 }),
+            json!({ "jsonrpc": "2.0", "result": "ok", "id": id }),
+            json!({ "jsonrpc": "2.0", "result": "stray", "id": 99 }),
+        );
+        let demux = ipc.clone();
+        let received = tokio::task::spawn_blocking(move || {
+            let mut received = Vec::new();
+            demux.demultiplex(io::Cursor::new(input), |msg| {
+                received.push(msg);
+                true
+            });
+            received
+        })
+        .await
+        .unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].as_ref().unwrap()["method"], "execute");
+        assert!(received[1].as_ref().unwrap_err().starts_with("Parse error"));
+
+        assert_eq!(answered.await.unwrap(), Ok(json!("ok")));
+        // EOF fails whoever is still waiting, and anyone who calls later.
+        assert_eq!(abandoned.await.unwrap(), Err("host has closed stdin".to_string()));
+        assert!(ipc.call("event.publish", json!({})).await.is_err());
+        assert!(ipc.pending.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,367 @@
+use crate::verification::VerifyError;
+use base64::Engine as _;
+use hmac::{Hmac, Mac};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Clock skew allowed on `exp` and `nbf` when the webhook doesn't say.
+pub const DEFAULT_LEEWAY_SECS: u64 = 60;
+
+const ALGORITHMS: &[&str] = &["HS256", "RS256", "ES256"];
+
+/// Settings for the `jwt` verification method. The token is read from
+/// `Authorization: Bearer <jwt>`. HS256 tokens are checked against the
+/// webhook's secrets, RS256 and ES256 tokens against the keys in
+/// `jwks_path`; a token must carry `exp`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JwtConfig {
+    /// Local JWKS file (`{"keys": [...]}`) with the sender's public keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwks_path: Option<String>,
+    /// Required `iss`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Accepted `aud` values; the token must name at least one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audience: Vec<String>,
+    /// Accepted `alg` values. Unset accepts any supported one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub algorithms: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leeway_seconds: Option<u64>,
+}
+
+impl JwtConfig {
+    /// Parse `jwt` from operation input and check it, including that the
+    /// JWKS file can be read.
+    pub fn parse(value: &Value) -> Result<Self, String> {
+        let mut value = value.clone();
+        // A single audience may be given as a string.
+        if let Some(aud) = value.get_mut("audience").filter(|a| a.is_string()) {
+            *aud = Value::Array(vec![aud.take()]);
+        }
+        let config: JwtConfig =
+            serde_json::from_value(value).map_err(|e| format!("invalid jwt: {e}"))?;
+        if let Some(alg) = config.algorithms.iter().find(|a| !ALGORITHMS.contains(&a.as_str())) {
+            return Err(format!(
+                "invalid jwt: unsupported algorithm '{alg}' (expected one of {})",
+                ALGORITHMS.join(", ")
+            ));
+        }
+        if let Some(path) = &config.jwks_path {
+            let jwks = Jwks::load(path)?;
+            if jwks.keys.is_empty() {
+                return Err(format!("invalid jwt: {path} has no RS256 or ES256 keys"));
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// A decoded but not yet verified token.
+pub struct Token<'a> {
+    pub alg: String,
+    kid: Option<String>,
+    claims: Value,
+    signing_input: &'a str,
+    signature: Vec<u8>,
+}
+
+impl<'a> Token<'a> {
+    pub fn decode(token: &'a str) -> Result<Self, VerifyError> {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let (signing_input, signature) = token.rsplit_once('.').ok_or(VerifyError::MalformedToken)?;
+        let (header, claims) = signing_input.split_once('.').ok_or(VerifyError::MalformedToken)?;
+        let json = |part: &str| -> Result<Value, VerifyError> {
+            let bytes = engine.decode(part).map_err(|_| VerifyError::MalformedToken)?;
+            serde_json::from_slice(&bytes).map_err(|_| VerifyError::MalformedToken)
+        };
+        let header = json(header)?;
+        let claims = json(claims)?;
+        if !claims.is_object() {
+            return Err(VerifyError::MalformedToken);
+        }
+        Ok(Self {
+            alg: header["alg"].as_str().ok_or(VerifyError::MalformedToken)?.to_string(),
+            kid: header["kid"].as_str().map(String::from),
+            claims,
+            signing_input,
+            signature: engine.decode(signature).map_err(|_| VerifyError::MalformedToken)?,
+        })
+    }
+
+    /// Check an HS256 signature against a shared secret.
+    pub fn verify_hmac(&self, secret: &str) -> Result<(), VerifyError> {
+        if self.alg != "HS256" {
+            return Err(VerifyError::BadSignature);
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .map_err(|_| VerifyError::MalformedSecret)?;
+        mac.update(self.signing_input.as_bytes());
+        mac.verify_slice(&self.signature)
+            .map_err(|_| VerifyError::BadSignature)
+    }
+
+    /// Check an RS256 or ES256 signature against the JWKS keys, restricted
+    /// to the token's `kid` when it has one.
+    pub fn verify_jwks(&self, jwks: &Jwks) -> Result<(), VerifyError> {
+        let mut candidates = jwks
+            .keys
+            .iter()
+            .filter(|k| k.alg == self.alg)
+            .filter(|k| self.kid.is_none() || k.kid == self.kid)
+            .peekable();
+        if candidates.peek().is_none() {
+            return Err(VerifyError::UnknownKey);
+        }
+        let message = self.signing_input.as_bytes();
+        let matched = candidates.any(|key| match &key.material {
+            KeyMaterial::Rsa { n, e } => RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, &self.signature)
+                .is_ok(),
+            KeyMaterial::P256(point) => {
+                UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(message, &self.signature)
+                    .is_ok()
+            }
+        });
+        if matched {
+            Ok(())
+        } else {
+            Err(VerifyError::BadSignature)
+        }
+    }
+
+    /// Check `exp`, `nbf`, `iss` and `aud` and return the claims. Only call
+    /// once the signature has been verified.
+    pub fn claims(self, config: &JwtConfig, now: i64) -> Result<Value, VerifyError> {
+        let leeway = config.leeway_seconds.unwrap_or(DEFAULT_LEEWAY_SECS) as i64;
+        let time = |name: &str| self.claims.get(name).map(|v| v.as_i64().ok_or(VerifyError::BadClaims));
+
+        let exp = time("exp").ok_or(VerifyError::BadClaims)??;
+        if now > exp.saturating_add(leeway) {
+            return Err(VerifyError::ExpiredToken);
+        }
+        if let Some(nbf) = time("nbf").transpose()? {
+            if now.saturating_add(leeway) < nbf {
+                return Err(VerifyError::BadTimestamp);
+            }
+        }
+        if let Some(issuer) = &config.issuer {
+            if self.claims["iss"].as_str() != Some(issuer.as_str()) {
+                return Err(VerifyError::BadClaims);
+            }
+        }
+        if !config.audience.is_empty() {
+            let aud: Vec<&str> = match &self.claims["aud"] {
+                Value::String(a) => vec![a.as_str()],
+                Value::Array(a) => a.iter().filter_map(|v| v.as_str()).collect(),
+                _ => Vec::new(),
+            };
+            if !aud.iter().any(|a| config.audience.iter().any(|want| want == a)) {
+                return Err(VerifyError::BadClaims);
+            }
+        }
+        Ok(self.claims)
+    }
+}
+
+/// Public keys from a JWKS file. Keys other than RSA and P-256 EC are
+/// skipped, as are keys marked for encryption.
+pub struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+struct Jwk {
+    kid: Option<String>,
+    alg: &'static str,
+    material: KeyMaterial,
+}
+
+enum KeyMaterial {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    /// Uncompressed point: 0x04 || x || y.
+    P256(Vec<u8>),
+}
+
+impl Jwks {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+        let doc: Value =
+            serde_json::from_str(&content).map_err(|e| format!("failed to parse {path}: {e}"))?;
+        let keys = doc["keys"]
+            .as_array()
+            .ok_or_else(|| format!("{path} is not a JWKS document (no 'keys' array)"))?;
+        Ok(Self {
+            keys: keys.iter().filter_map(Jwk::parse).collect(),
+        })
+    }
+}
+
+impl Jwk {
+    fn parse(key: &Value) -> Option<Self> {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let field = |name: &str| key[name].as_str().and_then(|v| engine.decode(v).ok());
+        if key["use"].as_str().is_some_and(|u| u != "sig") {
+            return None;
+        }
+        let (alg, material) = match (key["kty"].as_str()?, key["crv"].as_str()) {
+            ("RSA", _) => ("RS256", KeyMaterial::Rsa { n: field("n")?, e: field("e")? }),
+            ("EC", Some("P-256")) => {
+                let (x, y) = (field("x")?, field("y")?);
+                if x.len() != 32 || y.len() != 32 {
+                    return None;
+                }
+                let mut point = vec![0x04];
+                point.extend_from_slice(&x);
+                point.extend_from_slice(&y);
+                ("ES256", KeyMaterial::P256(point))
+            }
+            _ => return None,
+        };
+        if key["alg"].as_str().is_some_and(|a| a != alg) {
+            return None;
+        }
+        Some(Self {
+            kid: key["kid"].as_str().map(String::from),
+            alg,
+            material,
+        })
+    }
+}
+
+/// Loaded JWKS files, reloaded when they change on disk.
+#[derive(Default)]
+pub struct JwksCache {
+    entries: Mutex<HashMap<String, (SystemTime, Arc<Jwks>)>>,
+}
+
+impl JwksCache {
+    pub fn get(&self, path: &str) -> Result<Arc<Jwks>, String> {
+        let modified = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .map_err(|e| format!("failed to read {path}: {e}"))?;
+        let mut entries = self.entries.lock().unwrap();
+        if let Some((seen, jwks)) = entries.get(path) {
+            if *seen == modified {
+                return Ok(jwks.clone());
+            }
+        }
+        let jwks = Arc::new(Jwks::load(path)?);
+        entries.insert(path.to_string(), (modified, jwks.clone()));
+        Ok(jwks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hs256(secret: &str, claims: Value) -> String {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let input = format!(
+            "{}.{}",
+            engine.encode(br#"{"alg":"HS256","typ":"JWT"}"#),
+            engine.encode(claims.to_string())
+        );
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(input.as_bytes());
+        format!("{input}.{}", engine.encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn hs256_claims() {
+        let config = JwtConfig {
+            issuer: Some("billing".into()),
+            audience: vec!["receiver".into()],
+            ..Default::default()
+        };
+        let check = |token: &str, now| {
+            let token = Token::decode(token)?;
+            token.verify_hmac("s3cret")?;
+            token.claims(&config, now)
+        };
+        let good = hs256("s3cret", serde_json::json!({
+            "iss": "billing", "aud": ["other", "receiver"], "exp": 1000, "sub": "svc"
+        }));
+        assert_eq!(check(&good, 900).unwrap()["sub"], "svc");
+        assert_eq!(check(&good, 1061).unwrap_err(), VerifyError::ExpiredToken);
+        assert_eq!(
+            check(&hs256("other", serde_json::json!({ "exp": 1000 })), 900).unwrap_err(),
+            VerifyError::BadSignature
+        );
+        let wrong_iss = hs256("s3cret", serde_json::json!({ "iss": "x", "aud": "receiver", "exp": 1000 }));
+        assert_eq!(check(&wrong_iss, 900).unwrap_err(), VerifyError::BadClaims);
+        let no_exp = hs256("s3cret", serde_json::json!({ "iss": "billing", "aud": "receiver" }));
+        assert_eq!(check(&no_exp, 900).unwrap_err(), VerifyError::BadClaims);
+        assert_eq!(Token::decode("a.b").err(), Some(VerifyError::MalformedToken));
+    }
+
+    // RS256 example from RFC 7515, appendix A.2.
+    #[test]
+    fn rs256_rfc7515_vector() {
+        let jwks = Jwks {
+            keys: vec![Jwk::parse(&serde_json::json!({
+                "kty": "RSA",
+                "kid": "rfc7515",
+                "n": "ofgWCuLjybRlzo0tZWJjNiuSfb4p4fAkd_wWJcyQoTbji9k0l8W26mPddxHmfHQp-Vaw-4qPCJrcS2mJ\
+                      PMEzP1Pt0Bm4d4QlL-yRT-SFd2lZS-pCgNMsD1W_YpRPEwOWvG6b32690r2jZ47soMZo9wGzjb_7OMg0\
+                      LOL-bSf63kpaSHSXndS5z5rexMdbBYUsLA9e-KXBdQOS-UTo7WTBEMa2R2CapHg665xsmtdVMTBQY4uD\
+                      Zlxvb3qCo5ZwKh9kG4LT6_I5IhlJH7aGhyxXFvUK-DWNmoudF8NAco9_h9iaGNj8q2ethFkMLs91kzk2\
+                      PAcDTW9gb54h4FRWyuXpoQ",
+                "e": "AQAB",
+            }))
+            .unwrap()],
+        };
+        let claims = "eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFtcGxlLmNvbS9pc19yb290Ijp0cnVlfQ";
+        let signature = "cC4hiUPoj9Eetdgtv3hF80EGrhuB__dzERat0XF9g2VtQgr9PJbu3XOiZj5RZmh7AAuHIm4Bh-0Qc_lF\
+            5YKt_O8W2Fp5jujGbds9uJdbF9CUAr7t1dnZcAcQjbKBYNX4BAynRFdiuB--f_nZLgrnbyTyWzO75vRK5h6x\
+            BArLIARNPvkSjtQBMHlb1L07Qe7K0GarZRmB_eSN9383LcOLn6_dO--xi12jzDwusC-eOkHWEsqtFZESc6Bf\
+            I7noOPqvhJ1phCnvWh6IeYI2w9QOYEUipUTI8np6LbgGY9Fs98rqVt5AXLIhWkWywlVmtVrBp0igcN_IoypG\
+            lUPQGe77Rw";
+        let token = format!("eyJhbGciOiJSUzI1NiJ9.{claims}.{signature}");
+        let token = Token::decode(&token).unwrap();
+        assert_eq!(token.verify_jwks(&jwks), Ok(()));
+        assert_eq!(token.claims(&JwtConfig::default(), 1300819380).unwrap()["iss"], "joe");
+
+        // A kid narrows the keys tried; one naming no key in the set fails
+        // before any signature is checked.
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        let with_kid = |kid: &str| {
+            let header = engine.encode(format!(r#"{{"alg":"RS256","kid":"{kid}"}}"#));
+            format!("{header}.{claims}.{signature}")
+        };
+        let unknown = with_kid("rotated-away");
+        assert_eq!(Token::decode(&unknown).unwrap().verify_jwks(&jwks), Err(VerifyError::UnknownKey));
+        // Even the right kid changes the signed header, so the signature fails.
+        let known = with_kid("rfc7515");
+        assert_eq!(Token::decode(&known).unwrap().verify_jwks(&jwks), Err(VerifyError::BadSignature));
+    }
+
+    // ES256 example from RFC 7515, appendix A.3.
+    #[test]
+    fn es256_rfc7515_vector() {
+        let jwks = Jwks {
+            keys: vec![Jwk::parse(&serde_json::json!({
+                "kty": "EC",
+                "crv": "P-256",
+                "x": "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU",
+                "y": "x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0",
+            }))
+            .unwrap()],
+        };
+        let token = "eyJhbGciOiJFUzI1NiJ9\
+            .eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFtcGxlLmNvbS9pc19yb290Ijp0cnVlfQ\
+            .DtEhU3ljbEg8L38VWAfUAqOyKAM6-Xx-F4GawxaepmXFCgfTjDxw5djxLa8ISlSApmWQxfKTUJqPP3-Kg6NU1Q";
+        let token = Token::decode(token).unwrap();
+        assert_eq!(token.verify_jwks(&jwks), Ok(()));
+        assert_eq!(token.verify_hmac("x"), Err(VerifyError::BadSignature));
+        assert_eq!(token.claims(&JwtConfig::default(), 1300819380).unwrap()["iss"], "joe");
+    }
+}
@@ -0,0 +1,307 @@
+use crate::types::ServerSettings;
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Body size cap when none is configured. Matches the 2 MiB axum applied
+/// implicitly before limits were configurable.
+pub const DEFAULT_MAX_BODY_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Request limits. The server-wide set and a webhook's own set both apply:
+/// the smaller body cap wins, both rate limits must have room, and an
+/// address must pass both sets of CIDR lists.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Limits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimit>,
+    /// If non-empty, only these networks may send requests.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_cidrs: Vec<Cidr>,
+    /// These networks are always refused, even if allowed above.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny_cidrs: Vec<Cidr>,
+}
+
+/// Token bucket: refills at `requests_per_minute` and holds up to `burst`
+/// tokens (defaults to `requests_per_minute`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub requests_per_minute: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+}
+
+impl Limits {
+    pub fn is_empty(&self) -> bool {
+        self.max_body_bytes.is_none()
+            && self.rate_limit.is_none()
+            && self.allow_cidrs.is_empty()
+            && self.deny_cidrs.is_empty()
+    }
+
+    /// Parse `limits` from operation input and check it.
+    pub fn parse(value: &serde_json::Value) -> Result<Self, String> {
+        let limits: Limits = serde_json::from_value(value.clone())
+            .map_err(|e| format!("invalid limits: {e}"))?;
+        if limits.max_body_bytes == Some(0) {
+            return Err("invalid limits: max_body_bytes must be positive".into());
+        }
+        if let Some(rl) = &limits.rate_limit {
+            if rl.requests_per_minute == 0 || rl.burst == Some(0) {
+                return Err("invalid limits: rate_limit values must be positive".into());
+            }
+        }
+        Ok(limits)
+    }
+
+    /// Check a client address against the allow and deny lists, returning
+    /// the rejection reason if it fails.
+    pub fn check_ip(&self, ip: IpAddr) -> Result<(), &'static str> {
+        let matches = |list: &[Cidr]| list.iter().any(|c| c.contains(ip));
+        if matches(&self.deny_cidrs) {
+            return Err("ip_denied");
+        }
+        if !self.allow_cidrs.is_empty() && !matches(&self.allow_cidrs) {
+            return Err("ip_not_allowed");
+        }
+        Ok(())
+    }
+}
+
+/// The server-wide limits and trusted proxies, parsed from the settings.
+/// Shared with the HTTP server and swapped out when the settings change.
+#[derive(Debug, Clone, Default)]
+pub struct ServerLimits {
+    pub limits: Limits,
+    pub trusted_proxies: Vec<Cidr>,
+}
+
+impl ServerLimits {
+    pub fn from_settings(settings: &ServerSettings) -> Result<Self, String> {
+        Ok(Self {
+            limits: settings.limits.clone(),
+            trusted_proxies: parse_cidrs(&settings.trusted_proxies)?,
+        })
+    }
+}
+
+/// An IPv4 or IPv6 network. A bare address is a single-host network.
+/// Stored as its string form, and parsed once when read.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("invalid CIDR '{s}'"))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid CIDR prefix in '{s}'"))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, canonical(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        Self::parse(&s)
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        let max = if cidr.addr.is_ipv4() { 32 } else { 128 };
+        if cidr.prefix == max {
+            cidr.addr.to_string()
+        } else {
+            format!("{}/{}", cidr.addr, cidr.prefix)
+        }
+    }
+}
+
+/// Treat IPv4-mapped IPv6 addresses (from dual-stack sockets) as IPv4.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        v4 => v4,
+    }
+}
+
+pub fn parse_cidrs(list: &[String]) -> Result<Vec<Cidr>, String> {
+    list.iter().map(|s| Cidr::parse(s)).collect()
+}
+
+/// The address a request really came from. `X-Forwarded-For` is only
+/// believed when the peer is a trusted proxy; it is then read right to left,
+/// skipping further trusted proxies, so a client can't spoof its address by
+/// prepending entries.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[Cidr]) -> IpAddr {
+    let peer = canonical(peer);
+    let trusted = |ip: IpAddr| trusted_proxies.iter().any(|c| c.contains(ip));
+    if !trusted(peer) {
+        return peer;
+    }
+    let forwarded = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|hop| hop.trim().parse::<IpAddr>().ok())
+        .map(canonical)
+        .collect::<Vec<_>>();
+    forwarded
+        .into_iter()
+        .rev()
+        .find(|ip| !trusted(*ip))
+        .unwrap_or(peer)
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets keyed by scope (the webhook id, or `_global`).
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Take a token from `key`'s bucket, or return how long until one is
+    /// available.
+    pub fn take(&self, key: &str, limit: &RateLimit) -> Result<(), Duration> {
+        let rate = limit.requests_per_minute as f64 / 60.0;
+        let capacity = limit.burst.unwrap_or(limit.requests_per_minute) as f64;
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_matching() {
+        // One of GitHub's published hook ranges.
+        let github = Cidr::parse("192.30.252.0/22").unwrap();
+        assert!(github.contains(ip("192.30.255.1")));
+        assert!(!github.contains(ip("192.30.251.255")));
+        assert!(github.contains(ip("::ffff:192.30.253.7")));
+
+        let v6 = Cidr::parse("2a0a:a440::/29").unwrap();
+        assert!(v6.contains(ip("2a0a:a447::1")));
+        assert!(!v6.contains(ip("2a0a:a448::1")));
+
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(ip("203.0.113.9")));
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("github").is_err());
+    }
+
+    #[test]
+    fn cidrs_are_parsed_when_limits_are_read() {
+        let value = serde_json::json!({
+            "allow_cidrs": ["10.0.0.0/8", "192.0.2.7", "2001:db8::/32"],
+        });
+        let limits = Limits::parse(&value).unwrap();
+        assert_eq!(serde_json::to_value(&limits).unwrap(), value);
+
+        let err = Limits::parse(&serde_json::json!({ "deny_cidrs": ["10.0.0.0/40"] }))
+            .unwrap_err();
+        assert!(err.contains("invalid CIDR prefix"), "{err}");
+    }
+
+    #[test]
+    fn deny_overrides_allow() {
+        let limits = Limits::parse(&serde_json::json!({
+            "allow_cidrs": ["10.0.0.0/8"],
+            "deny_cidrs": ["10.1.0.0/16"],
+        }))
+        .unwrap();
+        assert_eq!(limits.check_ip(ip("10.2.3.4")), Ok(()));
+        assert_eq!(limits.check_ip(ip("10.1.3.4")), Err("ip_denied"));
+        assert_eq!(limits.check_ip(ip("192.168.0.1")), Err("ip_not_allowed"));
+    }
+
+    #[test]
+    fn forwarded_for_only_from_trusted_proxies() {
+        let proxies = parse_cidrs(&["127.0.0.1".into(), "10.0.0.0/8".into()]).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.2.3.4, 198.51.100.7, 10.0.0.5".parse().unwrap());
+
+        // The spoofable first entry is ignored; the nearest untrusted hop wins.
+        assert_eq!(client_ip(ip("127.0.0.1"), &headers, &proxies), ip("198.51.100.7"));
+        // Anyone else's header is ignored.
+        assert_eq!(client_ip(ip("203.0.113.9"), &headers, &proxies), ip("203.0.113.9"));
+        assert_eq!(client_ip(ip("127.0.0.1"), &HeaderMap::new(), &proxies), ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn rate_limit_burst() {
+        let limiter = RateLimiter::default();
+        let limit = RateLimit {
+            requests_per_minute: 60,
+            burst: Some(2),
+        };
+        assert!(limiter.take("wh", &limit).is_ok());
+        assert!(limiter.take("wh", &limit).is_ok());
+        let wait = limiter.take("wh", &limit).unwrap_err();
+        assert!(wait <= Duration::from_secs(1));
+        // Buckets are independent per scope.
+        assert!(limiter.take("_global", &limit).is_ok());
+    }
+}
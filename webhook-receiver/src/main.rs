@@ -1,19 +1,49 @@
+mod body;
+mod bridge;
+mod challenge;
+mod dedupe;
+mod event_type;
 mod http_server;
+mod ipc;
+mod jwt;
+mod limits;
+mod outbox;
+mod response;
+mod rules;
+mod secrets;
+mod selftest;
 mod store;
+mod tls;
 mod types;
 mod verification;
 
+use bridge::{PendingReplies, Reply, SyncReply};
 use chrono::Utc;
+use dedupe::DedupeCache;
+use event_type::EventTypeTemplate;
+use http_server::ServerConfig;
+use ipc::Ipc;
+use jwt::JwtConfig;
+use limits::Limits;
+use outbox::Outbox;
+use response::ResponseTemplate;
+use secrets::WebhookSecret;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
-use std::io::{self, BufRead, Write};
-use std::path::PathBuf;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
+use tls::CertResolver;
 use tokio::sync::{mpsc, RwLock};
-use types::{Delivery, PendingEvent, Webhook};
+use types::{Delivery, ServerSettings, Webhook};
 use uuid::Uuid;
 
+/// Rejected requests waiting to be recorded; beyond this they're dropped.
+const REJECTION_QUEUE: usize = 1024;
+
 // ─── JSON-RPC wire types ────────────────────────────────────────────────────
 
 #[derive(Deserialize)]
@@ -70,10 +100,15 @@ struct AppState {
     store: store::WebhookStore,
     /// Mirror of webhooks for the HTTP server (refreshed on mutation).
     webhook_map: http_server::WebhookMap,
-    port: Option<u16>,
+    /// Server-wide settings as seen by the HTTP server.
+    config: http_server::SharedConfig,
+    listen_addr: Option<SocketAddr>,
+    tls: Option<Arc<CertResolver>>,
     base_url: String,
-    /// Events received from HTTP server, not yet published to host.
-    pending_rx: Option<mpsc::UnboundedReceiver<(PendingEvent, Delivery)>>,
+    outbox: Option<Arc<Outbox>>,
+    dedupe: Option<Arc<DedupeCache>>,
+    /// HTTP requests held open for a reply from the host.
+    replies: Arc<PendingReplies>,
 }
 
 impl AppState {
@@ -81,10 +116,54 @@ impl AppState {
         Self {
             store: store::WebhookStore::new(data_dir),
             webhook_map: Arc::new(RwLock::new(HashMap::new())),
-            port: None,
+            config: Default::default(),
+            listen_addr: None,
+            tls: None,
             base_url: String::new(),
-            pending_rx: None,
+            outbox: None,
+            dedupe: None,
+            replies: Arc::new(PendingReplies::default()),
+        }
+    }
+
+    /// Recompute the base URL from the settings and listen address, and
+    /// point stored webhook URLs at it. URLs only change if the public base
+    /// URL changed or the listener had to fall back to another port.
+    fn apply_base_url(&mut self) {
+        let Some(addr) = self.listen_addr else {
+            return;
+        };
+        self.base_url = match &self.store.settings.public_base_url {
+            Some(public) => public.trim_end_matches('/').to_string(),
+            None => {
+                let host = match addr.ip() {
+                    IpAddr::V4(ip) if ip.is_unspecified() => "127.0.0.1".to_string(),
+                    IpAddr::V6(ip) if ip.is_unspecified() => "[::1]".to_string(),
+                    IpAddr::V6(ip) => format!("[{ip}]"),
+                    ip => ip.to_string(),
+                };
+                let scheme = if self.tls.is_some() { "https" } else { "http" };
+                format!("{scheme}://{host}:{}", addr.port())
+            }
+        };
+
+        let mut changed = 0;
+        for (id, wh) in self.store.webhooks.iter_mut() {
+            let url = format!("{}/hooks/{id}", self.base_url);
+            if wh.url != url {
+                wh.url = url;
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            eprintln!(
+                "webhook-receiver: {changed} webhook URL(s) changed to {}; \
+                 update any upstream registrations",
+                self.base_url
+            );
+            let _ = self.store.save_webhooks();
         }
+        self.refresh_webhook_map();
     }
 
     fn refresh_webhook_map(&self) {
@@ -103,72 +182,72 @@ impl AppState {
 async fn main() {
     let state: Arc<RwLock<AppState>> =
         Arc::new(RwLock::new(AppState::new(PathBuf::from("."))));
-
-    let state_clone = state.clone();
-    let handle = tokio::runtime::Handle::current();
-
-    // Stdin loop on a blocking thread to avoid blocking the async runtime.
-    tokio::task::spawn_blocking(move || {
-        let stdin = io::stdin();
-        let stdout = io::stdout();
-        let mut out = stdout.lock();
-        let mut line = String::new();
-
-        loop {
-            line.clear();
-            match stdin.lock().read_line(&mut line) {
-                Ok(0) | Err(_) => break,
-                _ => {}
-            }
-
-            let trimmed = line.trim();
-            if trimmed.is_empty() {
-                continue;
-            }
-
-            let request: JsonRpcRequest = match serde_json::from_str(trimmed) {
-                Ok(r) => r,
-                Err(e) => {
-                    let resp =
-                        err_response(Value::Number(0.into()), -32700, format!("Parse error: {e}"));
-                    emit_line(&mut out, &resp);
-                    continue;
+    let ipc = Ipc::new();
+
+    let (request_tx, mut request_rx) = mpsc::unbounded_channel::<JsonRpcRequest>();
+
+    // Stdin demultiplexer on a blocking thread. Host requests are queued for
+    // the dispatcher; replies to our own outbound calls are routed to the
+    // caller waiting on them.
+    let ipc_reader = ipc.clone();
+    std::thread::spawn(move || {
+        ipc_reader.demultiplex(io::stdin().lock(), |msg| {
+            let request = msg.map_err(|e| (-32700, e)).and_then(|msg| {
+                serde_json::from_value::<JsonRpcRequest>(msg)
+                    .map_err(|e| (-32600, format!("Invalid request: {e}")))
+            });
+            match request {
+                Ok(req) => request_tx.send(req).is_ok(),
+                Err((code, message)) => {
+                    ipc_reader.send(&err_response(Value::Number(0.into()), code, message));
+                    true
                 }
-            };
-
-            let is_shutdown = request.method == "shutdown";
-
-            let response =
-                handle.block_on(handle_request(&request, &state_clone, &mut out));
-            emit_line(&mut out, &response);
-
-            if is_shutdown {
-                break;
             }
+        });
+    });
+
+    // Requests are handled one at a time, in order.
+    while let Some(request) = request_rx.recv().await {
+        // A test delivery waits on the listener and the publish, and on a
+        // sync-reply webhook for `reply_to_delivery`, so it is answered from
+        // its own task rather than holding up the requests behind it.
+        if is_test_delivery(&request) {
+            let (state, ipc) = (state.clone(), ipc.clone());
+            tokio::spawn(async move {
+                let response = handle_request(&request, &state, &ipc).await;
+                ipc.send(&response);
+            });
+            continue;
         }
-    })
-    .await
-    .unwrap();
-}
+        let is_shutdown = request.method == "shutdown";
+        let response = handle_request(&request, &state, &ipc).await;
+        ipc.send(&response);
+        if is_shutdown {
+            break;
+        }
+    }
 
-fn emit_line(out: &mut impl Write, resp: &JsonRpcResponse) {
-    let line = serde_json::to_string(resp).expect("serialize response");
-    let _ = writeln!(out, "{line}");
-    let _ = out.flush();
+    // Give the writer thread a moment to flush the final response.
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
 }
 
 // ─── Request dispatch ─────────────────────────────────────────────────────────
 
+fn is_test_delivery(req: &JsonRpcRequest) -> bool {
+    req.method == "execute"
+        && req.params.get("operation").and_then(|v| v.as_str()) == Some("send_test_delivery")
+}
+
 async fn handle_request(
     req: &JsonRpcRequest,
     state: &Arc<RwLock<AppState>>,
-    out: &mut dyn Write,
+    ipc: &Arc<Ipc>,
 ) -> JsonRpcResponse {
     match req.method.as_str() {
-        "initialize" => handle_initialize(req, state).await,
+        "initialize" => handle_initialize(req, state, ipc).await,
         "shutdown" => handle_shutdown(req, state).await,
-        "execute" => handle_execute(req, state, out).await,
-        m if m.starts_with("resources.") => handle_resources(req, state, out).await,
+        "execute" => handle_execute(req, state).await,
+        m if m.starts_with("resources.") => handle_resources(req, state).await,
         _ => err_response(req.id.clone(), -32601, format!("Unknown method: {}", req.method)),
     }
 }
@@ -176,6 +255,7 @@ async fn handle_request(
 async fn handle_initialize(
     req: &JsonRpcRequest,
     state: &Arc<RwLock<AppState>>,
+    ipc: &Arc<Ipc>,
 ) -> JsonRpcResponse {
     let data_dir = req
         .params
@@ -184,42 +264,85 @@ async fn handle_initialize(
         .map(PathBuf::from)
         .unwrap_or_else(|| PathBuf::from("."));
 
-    let webhook_map = {
+    let (webhook_map, shared_config, replies) = {
         let st = state.read().await;
-        st.webhook_map.clone()
+        (st.webhook_map.clone(), st.config.clone(), st.replies.clone())
     };
 
-    let (port, pending_rx) = match http_server::start(webhook_map).await {
-        Ok(r) => r,
+    let outbox = match Outbox::open(&data_dir) {
+        Ok(o) => o,
         Err(e) => {
-            return err_response(req.id.clone(), -32603, format!("HTTP server failed: {e}"));
+            return err_response(req.id.clone(), -32603, format!("Outbox unavailable: {e}"));
         }
     };
 
-    let base_url = format!("http://127.0.0.1:{port}");
+    let dedupe = match DedupeCache::open(&data_dir) {
+        Ok(d) => d,
+        Err(e) => {
+            return err_response(req.id.clone(), -32603, format!("Dedupe cache unavailable: {e}"));
+        }
+    };
 
-    let mut st = state.write().await;
-    st.store = store::WebhookStore::new(data_dir);
-    st.port = Some(port);
-    st.base_url = base_url.clone();
-    st.pending_rx = Some(pending_rx);
+    let mut store = store::WebhookStore::new(data_dir.clone());
+    if let Err(e) = store.load() {
+        // Carrying on would overwrite the stored webhooks on the next save.
+        return err_response(req.id.clone(), -32603, format!("Failed to load state: {e}"));
+    }
+    let settings = store.settings.clone();
 
-    if let Err(e) = st.store.load() {
-        eprintln!("webhook-receiver: failed to load state: {e}");
+    match ServerConfig::from_settings(&settings) {
+        Ok(c) => *shared_config.write().unwrap() = c,
+        Err(e) => eprintln!("webhook-receiver: ignoring invalid server settings: {e}"),
     }
 
-    // Update all stored webhook URLs to reflect the current port
-    let ids: Vec<String> = st.store.webhooks.keys().cloned().collect();
-    for id in &ids {
-        if let Some(wh) = st.store.webhooks.get_mut(id) {
-            wh.url = format!("{base_url}/hooks/{id}");
+    let tls = if settings.tls {
+        match tls_resolver(&settings, &data_dir) {
+            Ok(r) => Some(r),
+            Err(e) => return err_response(req.id.clone(), -32603, format!("TLS unavailable: {e}")),
+        }
+    } else {
+        None
+    };
+
+    let (rejection_tx, rejection_rx) = mpsc::channel(REJECTION_QUEUE);
+
+    let addr = match http_server::start(
+        &settings.bind_address,
+        settings.port.or(settings.last_port),
+        tls.clone(),
+        webhook_map,
+        shared_config,
+        outbox.clone(),
+        dedupe.clone(),
+        replies,
+        rejection_tx,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return err_response(req.id.clone(), -32603, format!("HTTP server failed: {e}"));
+        }
+    };
+
+    let mut st = state.write().await;
+    st.store = store;
+    st.listen_addr = Some(addr);
+    st.tls = tls;
+    st.outbox = Some(outbox.clone());
+    st.dedupe = Some(dedupe);
+
+    if st.store.settings.last_port != Some(addr.port()) {
+        st.store.settings.last_port = Some(addr.port());
+        if let Err(e) = st.store.save_settings() {
+            eprintln!("webhook-receiver: failed to save server settings: {e}");
         }
     }
-    if !ids.is_empty() {
-        let _ = st.store.save_webhooks();
-    }
+    st.apply_base_url();
+    drop(st);
 
-    st.refresh_webhook_map();
+    tokio::spawn(publish_loop(state.clone(), ipc.clone(), outbox));
+    tokio::spawn(record_rejections(state.clone(), rejection_rx));
 
     JsonRpcResponse {
         jsonrpc: "2.0",
@@ -229,12 +352,50 @@ async fn handle_initialize(
     }
 }
 
+/// Load the configured certificate pair, or the self-signed one from the
+/// data dir (generated on first use for localhost, the bind address and the
+/// public host).
+fn tls_resolver(settings: &ServerSettings, data_dir: &Path) -> Result<Arc<CertResolver>, String> {
+    let (cert, key) = match (&settings.tls_cert_path, &settings.tls_key_path) {
+        (Some(cert), Some(key)) => (PathBuf::from(cert), PathBuf::from(key)),
+        (None, None) => {
+            let mut hosts: Vec<String> =
+                vec!["localhost".into(), "127.0.0.1".into(), "::1".into()];
+            let bind_host = settings
+                .bind_address
+                .parse::<IpAddr>()
+                .ok()
+                .filter(|ip| !ip.is_unspecified())
+                .map(|ip| ip.to_string());
+            let public_host = settings
+                .public_base_url
+                .as_deref()
+                .and_then(|u| u.split_once("://"))
+                .map(|(_, rest)| rest.split(['/', ':']).next().unwrap_or(rest).to_string());
+            for host in [bind_host, public_host].into_iter().flatten() {
+                if !host.is_empty() && !hosts.contains(&host) {
+                    hosts.push(host);
+                }
+            }
+            tls::ensure_self_signed(data_dir, hosts)?
+        }
+        _ => return Err("tls_cert_path and tls_key_path must be set together".into()),
+    };
+    CertResolver::new(cert, key)
+}
+
 async fn handle_shutdown(
     req: &JsonRpcRequest,
     state: &Arc<RwLock<AppState>>,
 ) -> JsonRpcResponse {
-    let st = state.read().await;
+    let mut st = state.write().await;
+    if let Err(e) = st.store.flush_unsaved() {
+        eprintln!("webhook-receiver: failed to save rejected requests: {e}");
+    }
     let _ = st.store.save_webhooks();
+    if let Some(dedupe) = &st.dedupe {
+        dedupe.flush().await;
+    }
     JsonRpcResponse {
         jsonrpc: "2.0",
         result: Some(serde_json::json!({})),
@@ -245,13 +406,7 @@ async fn handle_shutdown(
 
 // ─── Execute handler ──────────────────────────────────────────────────────────
 
-async fn handle_execute(
-    req: &JsonRpcRequest,
-    state: &Arc<RwLock<AppState>>,
-    out: &mut dyn Write,
-) -> JsonRpcResponse {
-    drain_pending_events(state, out).await;
-
+async fn handle_execute(req: &JsonRpcRequest, state: &Arc<RwLock<AppState>>) -> JsonRpcResponse {
     let operation = req
         .params
         .get("operation")
@@ -265,7 +420,15 @@ async fn handle_execute(
 
     let result = match operation {
         "get_server_info" => op_get_server_info(state).await,
+        "configure_server" => op_configure_server(&input, state).await,
         "get_recent_deliveries" => op_get_recent_deliveries(&input, state).await,
+        "get_outbox_status" => op_get_outbox_status(state).await,
+        "get_unmatched_requests" => op_get_unmatched_requests(state).await,
+        "replay_delivery" => op_replay_delivery(&input, state).await,
+        "replay_range" => op_replay_range(&input, state).await,
+        "reply_to_delivery" => op_reply_to_delivery(&input, state).await,
+        "rotate_secret" => op_rotate_secret(&input, state).await,
+        "send_test_delivery" => op_send_test_delivery(&input, state).await,
         "list_webhooks" => op_list_webhooks(state).await,
         "create_webhook" => op_create_webhook(&input, state).await,
         "update_webhook" => op_update_webhook(&input, state).await,
@@ -281,13 +444,7 @@ async fn handle_execute(
 
 // ─── Resources handler ────────────────────────────────────────────────────────
 
-async fn handle_resources(
-    req: &JsonRpcRequest,
-    state: &Arc<RwLock<AppState>>,
-    out: &mut dyn Write,
-) -> JsonRpcResponse {
-    drain_pending_events(state, out).await;
-
+async fn handle_resources(req: &JsonRpcRequest, state: &Arc<RwLock<AppState>>) -> JsonRpcResponse {
     let resource_method = req.method.strip_prefix("resources.").unwrap_or("");
     let resource_type = req
         .params
@@ -344,62 +501,126 @@ async fn handle_resources(
     }
 }
 
-// ─── Pending event draining ───────────────────────────────────────────────────
-
-/// Drain any deliveries queued by the HTTP server and publish them as IPC events.
-/// Must be called while the host is in its read loop (i.e., during any execute/resources call).
-async fn drain_pending_events(
-    state: &Arc<RwLock<AppState>>,
-    out: &mut dyn Write,
-) {
-    let mut pending: Vec<(PendingEvent, Delivery)> = Vec::new();
-    {
-        let mut st = state.write().await;
-        if let Some(rx) = &mut st.pending_rx {
-            while let Ok(item) = rx.try_recv() {
-                pending.push(item);
+// ─── Event publishing ─────────────────────────────────────────────────────────
+
+/// Upper bound on the delay between retries of a failing publish.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Publish attempts before an entry is given up on.
+const MAX_PUBLISH_ATTEMPTS: u32 = 10;
+
+/// Drain the outbox in order, publishing each delivery as a CloudEvent and
+/// removing it only once the host acknowledges. A failing entry is retried
+/// with exponential backoff and blocks the ones behind it, so per-webhook
+/// ordering is preserved. After `MAX_PUBLISH_ATTEMPTS` it is logged as a
+/// `publish_failed` delivery instead, where it can be replayed, so one bad
+/// entry can't hold up every webhook for good.
+async fn publish_loop(state: Arc<RwLock<AppState>>, ipc: Arc<Ipc>, outbox: Arc<Outbox>) {
+    loop {
+        let Some(entry) = outbox.front().await else {
+            outbox.wait().await;
+            continue;
+        };
+
+        let params = serde_json::json!({
+            "type": entry.event.event_type,
+            "subject": entry.event.subject,
+            "data": event_data(&entry),
+        });
+        let mut delivery = entry.delivery;
+        if let Err(e) = ipc.call("event.publish", params).await {
+            let attempts = outbox.record_failure(entry.id, e.clone()).await;
+            if attempts < MAX_PUBLISH_ATTEMPTS {
+                let backoff = Duration::from_secs(1u64 << attempts.min(6)).min(MAX_RETRY_BACKOFF);
+                eprintln!(
+                    "webhook-receiver: publish of delivery {} (seq {}) failed \
+                     (attempt {attempts}), retrying in {backoff:?}: {e}",
+                    delivery.id, entry.sequence
+                );
+                tokio::time::sleep(backoff).await;
+                continue;
             }
+            eprintln!(
+                "webhook-receiver: giving up on delivery {} (seq {}) after {attempts} attempts: {e}",
+                delivery.id, entry.sequence
+            );
+            delivery.status = "publish_failed".into();
+            delivery.reason = Some(e);
         }
-    }
 
-    if pending.is_empty() {
-        return;
-    }
+        outbox.ack(entry.id).await;
 
-    static NEXT_ID: std::sync::atomic::AtomicU64 =
-        std::sync::atomic::AtomicU64::new(20000);
-    let stdin = io::stdin();
-
-    for (event, _delivery) in &pending {
-        let call_id = NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        let req = serde_json::json!({
-            "jsonrpc": "2.0",
-            "method": "event.publish",
-            "params": {
-                "type": event.event_type,
-                "subject": event.subject,
-                "data": event.data,
-            },
-            "id": call_id,
-        });
-        let _ = writeln!(out, "{}", serde_json::to_string(&req).unwrap());
-        let _ = out.flush();
+        let mut st = state.write().await;
+        let wh_id = delivery.webhook_id.clone();
+        st.store.record_delivery(delivery);
+        let _ = st.store.save_deliveries(&wh_id);
+        let _ = st.store.save_webhooks();
+        st.refresh_webhook_map();
+    }
+}
 
-        // Read back the host's response to keep the protocol in sync
-        let mut line = String::new();
-        let _ = stdin.lock().read_line(&mut line);
+/// The published event's `data`: the delivery payload plus what the
+/// receiver knows about it. The host only carries `type`, `subject` and
+/// `data`, so anything a consumer needs has to go in here. A payload that
+/// isn't an object is moved under `payload` when there is something to add.
+fn event_data(entry: &outbox::OutboxEntry) -> Value {
+    let mut extra = serde_json::Map::new();
+    if let Some(original) = &entry.event.replayed_from {
+        extra.insert("replayed_from".into(), Value::String(original.clone()));
+    }
+    if let Some(correlation_id) = &entry.event.correlation_id {
+        extra.insert("correlation_id".into(), Value::String(correlation_id.clone()));
     }
+    if let Some(claims) = &entry.delivery.claims {
+        extra.insert("claims".into(), claims.clone());
+    }
+    if entry.delivery.headers.get(selftest::TEST_ID_HEADER).is_some() {
+        extra.insert("test".into(), Value::Bool(true));
+    }
+    if extra.is_empty() {
+        return entry.event.data.clone();
+    }
+    let mut data = match &entry.event.data {
+        Value::Object(fields) => fields.clone(),
+        payload => serde_json::Map::from_iter([("payload".to_string(), payload.clone())]),
+    };
+    data.extend(extra);
+    Value::Object(data)
+}
 
-    // Persist deliveries after publishing
-    {
-        let mut st = state.write().await;
-        for (_, delivery) in pending {
-            let wh_id = delivery.webhook_id.clone();
-            st.store.record_delivery(delivery);
-            let _ = st.store.save_deliveries(&wh_id);
+/// How often rejected and skipped requests recorded in memory are written
+/// out. Batching keeps a flood the limits turn away from becoming a flood of
+/// disk writes.
+const REJECTION_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Record requests the HTTP server turned away or filtered out, writing them
+/// to disk every `REJECTION_FLUSH_INTERVAL` and on shutdown.
+async fn record_rejections(state: Arc<RwLock<AppState>>, mut rx: mpsc::Receiver<Delivery>) {
+    let mut flush = tokio::time::interval(REJECTION_FLUSH_INTERVAL);
+    flush.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            received = rx.recv() => {
+                let Some(delivery) = received else { break };
+                // Take whatever else is already queued under the same lock.
+                let queued = std::iter::from_fn(|| rx.try_recv().ok());
+                let mut st = state.write().await;
+                for delivery in std::iter::once(delivery).chain(queued) {
+                    match delivery.status.as_str() {
+                        "filtered" | "duplicate" | "handshake" => st.store.record_skipped(delivery),
+                        _ => st.store.record_rejection(delivery),
+                    }
+                }
+            }
+            _ = flush.tick() => {
+                let mut st = state.write().await;
+                if st.store.has_unsaved() {
+                    if let Err(e) = st.store.flush_unsaved() {
+                        eprintln!("webhook-receiver: failed to save rejected requests: {e}");
+                    }
+                }
+            }
         }
-        let _ = st.store.save_webhooks();
-        st.refresh_webhook_map();
     }
 }
 
@@ -407,14 +628,159 @@ async fn drain_pending_events(
 
 async fn op_get_server_info(state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
     let st = state.read().await;
-    let port = st.port.ok_or("server not initialized")?;
+    let addr = st.listen_addr.ok_or("server not initialized")?;
+    let outbox = match &st.outbox {
+        Some(outbox) => outbox.status().await,
+        None => Value::Null,
+    };
+    let publish_failed = st
+        .store
+        .deliveries
+        .values()
+        .flatten()
+        .filter(|d| d.status == "publish_failed")
+        .count();
+    let settings = &st.store.settings;
     Ok(serde_json::json!({
-        "port": port,
+        "port": addr.port(),
+        "listen_address": addr.to_string(),
         "base_url": st.base_url,
+        "public_base_url": settings.public_base_url,
+        "configured_bind_address": settings.bind_address,
+        "configured_port": settings.port,
+        "tls": st.tls.as_ref().map(|t| serde_json::json!({
+            "fingerprint_sha256": t.fingerprint(),
+            "cert_path": t.cert_path().display().to_string(),
+            "self_signed": settings.tls_cert_path.is_none(),
+        })),
+        "limits": settings.limits,
+        "trusted_proxies": settings.trusted_proxies,
+        "redacted_headers": http_server::redacted_header_patterns(settings),
         "webhook_count": st.store.webhooks.len(),
+        "outbox": {
+            "pending_count": outbox["pending_count"],
+            "retrying_count": outbox["retrying_count"],
+            "publish_failed_count": publish_failed,
+        },
+    }))
+}
+
+/// Change listener settings. The public base URL, limits, trusted proxies
+/// and redacted headers apply immediately; bind address, port and TLS take effect on the
+/// next start.
+async fn op_configure_server(input: &Value, state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
+    let mut st = state.write().await;
+    let mut settings = st.store.settings.clone();
+
+    if let Some(v) = input.get("bind_address").and_then(|v| v.as_str()) {
+        v.parse::<IpAddr>()
+            .map_err(|e| format!("invalid bind_address '{v}': {e}"))?;
+        settings.bind_address = v.to_string();
+    }
+    if let Some(v) = input.get("port") {
+        // null or 0 goes back to reusing the last bound port.
+        settings.port = match v {
+            Value::Null => None,
+            v => match v.as_u64().ok_or("port must be an integer")? {
+                0 => None,
+                p => Some(u16::try_from(p).map_err(|_| format!("invalid port: {p}"))?),
+            },
+        };
+    }
+    if let Some(v) = input.get("public_base_url") {
+        settings.public_base_url = match v.as_str() {
+            None | Some("") => None,
+            Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+                Some(url.trim_end_matches('/').to_string())
+            }
+            Some(url) => {
+                return Err(format!("public_base_url must start with http:// or https://: {url}"))
+            }
+        };
+    }
+
+    if let Some(v) = input.get("tls").and_then(|v| v.as_bool()) {
+        settings.tls = v;
+    }
+    // Present-but-empty clears a path; absent leaves it alone.
+    let path = |field: &str| {
+        input
+            .get(field)
+            .map(|v| v.as_str().filter(|s| !s.is_empty()).map(String::from))
+    };
+    if let Some(v) = path("tls_cert_path") {
+        settings.tls_cert_path = v;
+    }
+    if let Some(v) = path("tls_key_path") {
+        settings.tls_key_path = v;
+    }
+    if settings.tls_cert_path.is_some() != settings.tls_key_path.is_some() {
+        return Err("tls_cert_path and tls_key_path must be set together".into());
+    }
+    if let Some(v) = input.get("limits") {
+        settings.limits = match v {
+            Value::Null => Limits::default(),
+            v => Limits::parse(v)?,
+        };
+    }
+    if let Some(v) = input.get("trusted_proxies") {
+        settings.trusted_proxies = match v {
+            Value::Null => Vec::new(),
+            v => serde_json::from_value(v.clone())
+                .map_err(|e| format!("invalid trusted_proxies: {e}"))?,
+        };
+    }
+    if let Some(v) = input.get("redacted_headers") {
+        settings.redacted_headers = match v {
+            Value::Null => None,
+            v => Some(
+                serde_json::from_value(v.clone())
+                    .map_err(|e| format!("invalid redacted_headers: {e}"))?,
+            ),
+        };
+    }
+    let server_config = ServerConfig::from_settings(&settings)?;
+
+    let current = &st.store.settings;
+    let listener_changed = settings.bind_address != current.bind_address
+        || settings.port != current.port
+        || settings.tls != current.tls
+        || settings.tls_cert_path != current.tls_cert_path
+        || settings.tls_key_path != current.tls_key_path;
+    st.store.settings = settings;
+    st.store.save_settings()?;
+    *st.config.write().unwrap() = server_config;
+    st.apply_base_url();
+
+    Ok(serde_json::json!({
+        "bind_address": st.store.settings.bind_address,
+        "port": st.store.settings.port,
+        "public_base_url": st.store.settings.public_base_url,
+        "tls": st.store.settings.tls,
+        "tls_cert_path": st.store.settings.tls_cert_path,
+        "tls_key_path": st.store.settings.tls_key_path,
+        "limits": st.store.settings.limits,
+        "trusted_proxies": st.store.settings.trusted_proxies,
+        "redacted_headers": http_server::redacted_header_patterns(&st.store.settings),
+        "base_url": st.base_url,
+        "restart_required": listener_changed,
     }))
 }
 
+async fn op_get_outbox_status(state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
+    let outbox = {
+        let st = state.read().await;
+        st.outbox.clone().ok_or("server not initialized")?
+    };
+    Ok(outbox.status().await)
+}
+
+async fn op_get_unmatched_requests(state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
+    let st = state.read().await;
+    let requests: Vec<&Delivery> = st.store.unmatched.iter().rev().collect();
+    Ok(serde_json::json!({ "requests": requests }))
+}
+
 async fn op_get_recent_deliveries(
     input: &Value,
     state: &Arc<RwLock<AppState>>,
@@ -424,6 +790,8 @@ async fn op_get_recent_deliveries(
         .and_then(|v| v.as_str())
         .ok_or("missing required field: webhook_id")?;
 
+    let status = input.get("status").and_then(|v| v.as_str());
+
     let st = state.read().await;
     if !st.store.webhooks.contains_key(webhook_id) {
         return Err(format!("webhook not found: {webhook_id}"));
@@ -433,21 +801,390 @@ async fn op_get_recent_deliveries(
         .store
         .get_deliveries(webhook_id)
         .into_iter()
+        .filter(|d| status.is_none_or(|s| d.status == s))
         .map(|d| serde_json::to_value(d).unwrap_or(Value::Null))
         .collect();
 
     Ok(serde_json::json!({ "deliveries": deliveries }))
 }
 
+/// Queue copies of stored deliveries for publishing again. Each copy gets a
+/// fresh id and sequence number and points back at the original. The
+/// webhook's current rules apply; returns the new delivery ids and how many
+/// originals the rules dropped. Nothing is queued unless every original can
+/// be replayed.
+///
+/// Copies carry the stored headers, which have had sensitive values
+/// redacted, so rules and event type templates reading such a header see
+/// `[redacted]` rather than the original value.
+async fn replay_deliveries(
+    originals: Vec<Delivery>,
+    state: &Arc<RwLock<AppState>>,
+) -> Result<(Vec<String>, usize), String> {
+    let (outbox, webhooks) = {
+        let st = state.read().await;
+        let outbox = st.outbox.clone().ok_or("server not initialized")?;
+        (outbox, st.store.webhooks.clone())
+    };
+
+    for original in &originals {
+        if !webhooks.contains_key(&original.webhook_id) {
+            return Err(format!("webhook not found: {}", original.webhook_id));
+        }
+        if original.body_sha256.is_some() && original.body.is_null() {
+            return Err(format!(
+                "delivery {} was rejected and its body was not stored",
+                original.id
+            ));
+        }
+    }
+
+    let mut replayed = Vec::with_capacity(originals.len());
+    let mut filtered = 0;
+    for original in originals {
+        let wh = &webhooks[&original.webhook_id];
+        let delivery = Delivery {
+            id: Uuid::new_v4().to_string(),
+            received_at: Utc::now(),
+            status: "success".to_string(),
+            sequence: None,
+            replayed_from: Some(original.id.clone()),
+            reason: None,
+            ..original
+        };
+        let Ok(event) = http_server::event_for(wh, &delivery) else {
+            filtered += 1;
+            continue;
+        };
+        replayed.push(delivery.id.clone());
+        outbox.enqueue(event, delivery).await?;
+    }
+    Ok((replayed, filtered))
+}
+
+async fn op_replay_delivery(input: &Value, state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
+    let delivery_id = input
+        .get("delivery_id")
+        .and_then(|v| v.as_str())
+        .ok_or("missing required field: delivery_id")?;
+
+    let original = {
+        let st = state.read().await;
+        st.store
+            .find_delivery(delivery_id)
+            .cloned()
+            .ok_or_else(|| format!("delivery not found: {delivery_id}"))?
+    };
+
+    let (replayed, filtered) = replay_deliveries(vec![original], state).await?;
+    Ok(serde_json::json!({
+        "replayed": replayed.len(),
+        "filtered": filtered,
+        "delivery_ids": replayed,
+    }))
+}
+
+async fn op_replay_range(input: &Value, state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
+    let webhook_id = input
+        .get("webhook_id")
+        .and_then(|v| v.as_str())
+        .ok_or("missing required field: webhook_id")?;
+    let parse_time = |field: &str| -> Result<Option<chrono::DateTime<Utc>>, String> {
+        input
+            .get(field)
+            .and_then(|v| v.as_str())
+            .map(|s| s.parse().map_err(|e| format!("invalid {field}: {e}")))
+            .transpose()
+    };
+    let since = parse_time("since")?;
+    let until = parse_time("until")?;
+    // Rejected deliveries failed verification, so never replay them unless asked.
+    let status = input
+        .get("status")
+        .and_then(|v| v.as_str())
+        .unwrap_or("success");
+
+    let originals: Vec<Delivery> = {
+        let st = state.read().await;
+        if !st.store.webhooks.contains_key(webhook_id) {
+            return Err(format!("webhook not found: {webhook_id}"));
+        }
+        let mut matching: Vec<Delivery> = st
+            .store
+            .get_deliveries(webhook_id)
+            .into_iter()
+            .filter(|d| d.status == status)
+            .filter(|d| since.is_none_or(|t| d.received_at >= t))
+            .filter(|d| until.is_none_or(|t| d.received_at <= t))
+            .cloned()
+            .collect();
+        // Stored newest-first; replay in the order they originally arrived.
+        matching.reverse();
+        matching
+    };
+
+    let (replayed, filtered) = replay_deliveries(originals, state).await?;
+    Ok(serde_json::json!({
+        "replayed": replayed.len(),
+        "filtered": filtered,
+        "delivery_ids": replayed,
+    }))
+}
+
+/// Answer an HTTP request held open by a sync-reply webhook.
+async fn op_reply_to_delivery(input: &Value, state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
+    let correlation_id = input
+        .get("correlation_id")
+        .and_then(|v| v.as_str())
+        .ok_or("missing required field: correlation_id")?;
+    let reply = Reply::parse(input)?;
+
+    let replies = state.read().await.replies.clone();
+    if !replies.resolve(correlation_id, reply) {
+        return Err(format!(
+            "no request is waiting for {correlation_id} (timed out or already answered)"
+        ));
+    }
+    Ok(serde_json::json!({ "delivered": true }))
+}
+
+/// Add a new secret to a webhook and schedule the current ones to expire
+/// after an overlap window, so the sender can be switched over without
+/// failed deliveries. The new value is only ever returned here.
+async fn op_rotate_secret(input: &Value, state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
+    let webhook_id = input
+        .get("webhook_id")
+        .and_then(|v| v.as_str())
+        .ok_or("missing required field: webhook_id")?;
+    let overlap = input
+        .get("overlap_seconds")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(secrets::DEFAULT_OVERLAP_SECS);
+    let supplied = input
+        .get("value")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty());
+
+    let mut st = state.write().await;
+    let wh = st
+        .store
+        .webhooks
+        .get_mut(webhook_id)
+        .ok_or_else(|| format!("webhook not found: {webhook_id}"))?;
+
+    let now = Utc::now();
+    let current = wh.secrets.iter().rev().find(|s| s.is_active(now));
+    let value = match supplied {
+        Some(v) => v.to_string(),
+        None => secrets::generate(&wh.verification_method, current.map(|s| s.value.as_str()))?,
+    };
+
+    let expires_at = i64::try_from(overlap)
+        .ok()
+        .and_then(chrono::Duration::try_seconds)
+        .and_then(|d| now.checked_add_signed(d))
+        .ok_or_else(|| format!("invalid overlap_seconds: {overlap}"))?;
+    secrets::retire_active(&mut wh.secrets, expires_at, now);
+    let secret = WebhookSecret::new(value);
+    let (id, value) = (secret.id.clone(), secret.value.clone());
+    wh.secrets.push(secret);
+
+    st.store.save_webhooks()?;
+    st.refresh_webhook_map();
+
+    Ok(serde_json::json!({
+        "secret_id": id,
+        "secret": value,
+        "previous_expires_at": expires_at.to_rfc3339(),
+    }))
+}
+
+/// How long `send_test_delivery` waits for the delivery to be published.
+const TEST_PUBLISH_WAIT: Duration = Duration::from_secs(5);
+
+/// Sign a sample (or supplied) payload the way the webhook's verification
+/// method expects, POST it to our own listener and report each stage. The
+/// delivery goes through the normal pipeline and is really published, with
+/// `test: true` in its data.
+async fn op_send_test_delivery(input: &Value, state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
+    let webhook_id = input
+        .get("webhook_id")
+        .and_then(|v| v.as_str())
+        .ok_or("missing required field: webhook_id")?;
+    let (wh, listen_addr, tls, outbox) = {
+        let st = state.read().await;
+        let wh = st
+            .store
+            .get(webhook_id)
+            .cloned()
+            .ok_or_else(|| format!("webhook not found: {webhook_id}"))?;
+        let listen_addr = st.listen_addr.ok_or("server not initialized")?;
+        (wh, listen_addr, st.tls.clone(), st.outbox.clone())
+    };
+
+    let now = Utc::now();
+    let test_id = Uuid::new_v4().to_string();
+    let mut payload = match input.get("body") {
+        Some(v) if !v.is_null() => v.clone(),
+        _ => selftest::sample_body(&wh, &test_id, now),
+    };
+    // Linear signs no timestamp; the verifier reads it from the body.
+    if wh.verification_method == "linear" {
+        if let Some(obj) = payload.as_object_mut() {
+            obj.entry("webhookTimestamp").or_insert(now.timestamp_millis().into());
+        }
+    }
+    let content_type = match (input.get("content_type").and_then(|v| v.as_str()), &payload) {
+        (Some(ct), _) => ct.to_string(),
+        (None, Value::String(_)) => "text/plain; charset=utf-8".to_string(),
+        (None, _) => "application/json".to_string(),
+    };
+    let raw = match &payload {
+        Value::String(text) => text.clone().into_bytes(),
+        other => other.to_string().into_bytes(),
+    };
+
+    let signed = selftest::sign(&wh, &raw, &content_type, now);
+    let mut headers: Vec<(String, String)> = vec![("content-type".into(), content_type)];
+    headers.extend(signed.headers);
+    // Caller-supplied headers win, e.g. a token signed by the real sender.
+    if let Some(extra) = input.get("headers").and_then(|v| v.as_object()) {
+        for (name, value) in extra {
+            let value = value.as_str().ok_or_else(|| format!("header '{name}' must be a string"))?;
+            let name = name.to_ascii_lowercase();
+            headers.retain(|(n, _)| *n != name);
+            headers.push((name, value.to_string()));
+        }
+    }
+    headers.push((selftest::TEST_ID_HEADER.into(), test_id.clone()));
+
+    // What the rules make of it, independent of whether it gets through.
+    let mut header_map = axum::http::HeaderMap::new();
+    for (name, value) in &headers {
+        if let (Ok(name), Ok(value)) = (
+            axum::http::HeaderName::try_from(name.as_str()),
+            axum::http::HeaderValue::from_str(value),
+        ) {
+            header_map.insert(name, value);
+        }
+    }
+    let preview = Delivery {
+        id: test_id.clone(),
+        webhook_id: wh.id.clone(),
+        received_at: now,
+        status: "success".to_string(),
+        headers: headers.iter().map(|(n, v)| (n.clone(), Value::String(v.clone()))).collect(),
+        body: body::decode(&header_map, &raw, limits::DEFAULT_MAX_BODY_BYTES),
+        query: body::query_params(signed.query.as_deref()),
+        sequence: None,
+        replayed_from: None,
+        reason: None,
+        body_sha256: None,
+        source_addr: None,
+        secret_id: None,
+        claims: None,
+    };
+    let transform = match http_server::event_for(&wh, &preview) {
+        Ok(event) => serde_json::json!({
+            "result": "publish",
+            "event_type": event.event_type,
+            "data": event.data,
+        }),
+        Err(reason) => serde_json::json!({ "result": "filtered", "reason": reason }),
+    };
+
+    let path = match &signed.query {
+        Some(query) => format!("/hooks/{}?{query}", wh.id),
+        None => format!("/hooks/{}", wh.id),
+    };
+    let timeout = Duration::from_millis(wh.sync_reply.as_ref().map_or(0, |s| s.timeout_ms))
+        + Duration::from_secs(10);
+    let sent = selftest::send(listen_addr, tls, &path, &headers, raw, timeout).await?;
+    let accepted = (200..300).contains(&sent.status);
+
+    // Find the delivery the request produced. Accepted ones are recorded
+    // once published; anything else as soon as it is turned away.
+    let is_test = |d: &Delivery| d.headers[selftest::TEST_ID_HEADER] == test_id.as_str();
+    let deadline = tokio::time::Instant::now()
+        + if accepted { TEST_PUBLISH_WAIT } else { Duration::from_secs(1) };
+    let recorded = loop {
+        let found = {
+            let st = state.read().await;
+            st.store.get_deliveries(&wh.id).into_iter().find(|d| is_test(d)).cloned()
+        };
+        if found.is_some() || tokio::time::Instant::now() >= deadline {
+            break found;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+
+    let mut verification = match recorded.as_ref().map(|d| d.status.as_str()) {
+        Some("verification_failed") => serde_json::json!({ "result": "failed" }),
+        Some("success" | "filtered" | "duplicate") => serde_json::json!({ "result": "passed" }),
+        Some(_) => serde_json::json!({ "result": "not_reached" }),
+        None if accepted => serde_json::json!({ "result": "passed" }),
+        None if sent.status == 401 => serde_json::json!({ "result": "failed" }),
+        None => serde_json::json!({ "result": "not_reached" }),
+    };
+    let publish = match &recorded {
+        Some(d) if d.status == "success" => serde_json::json!({ "result": "published" }),
+        Some(d) if matches!(d.status.as_str(), "filtered" | "duplicate") => {
+            serde_json::json!({ "result": "skipped", "reason": d.reason })
+        }
+        Some(_) => serde_json::json!({ "result": "not_attempted" }),
+        None if !accepted => serde_json::json!({ "result": "not_attempted" }),
+        None => {
+            let pending = match &outbox {
+                Some(outbox) => outbox.find(is_test).await,
+                None => None,
+            };
+            match pending {
+                Some(entry) => serde_json::json!({
+                    "result": "pending",
+                    "attempts": entry.attempts,
+                    "last_error": entry.last_error,
+                }),
+                None => serde_json::json!({ "result": "unknown" }),
+            }
+        }
+    };
+    if let Some(d) = &recorded {
+        if let Some(reason) = d.reason.as_ref().filter(|_| verification["result"] != "passed") {
+            verification["reason"] = Value::String(reason.clone());
+        }
+        if let Some(secret_id) = &d.secret_id {
+            verification["secret_id"] = Value::String(secret_id.clone());
+        }
+        if let Some(claims) = &d.claims {
+            verification["claims"] = claims.clone();
+        }
+    }
+
+    Ok(serde_json::json!({
+        "webhook_id": wh.id,
+        "delivery_id": recorded.as_ref().map(|d| d.id.clone()),
+        "request": {
+            "path": path,
+            "headers": headers.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(),
+            "unsigned_reason": signed.unsigned_reason,
+        },
+        "response": { "status": sent.status, "body": sent.body },
+        "verification": verification,
+        "transform": transform,
+        "publish": publish,
+    }))
+}
+
 // ─── MCP-exposed operations (delegate to resource CRUD) ──────────────────
 
 async fn op_list_webhooks(state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
     let st = state.read().await;
+    let cipher = st.store.cipher()?;
     let items: Vec<Value> = st
         .store
         .list()
         .into_iter()
-        .map(|w| serde_json::to_value(w).unwrap_or(Value::Null))
+        .map(|wh| secrets::redacted(wh, cipher))
         .collect();
     Ok(serde_json::json!({ "webhooks": items, "count": items.len() }))
 }
@@ -476,11 +1213,12 @@ async fn op_delete_webhook(input: &Value, state: &Arc<RwLock<AppState>>) -> Resu
 
 async fn resource_list(state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
     let st = state.read().await;
+    let cipher = st.store.cipher()?;
     let items: Vec<Value> = st
         .store
         .list()
         .into_iter()
-        .map(|w| serde_json::to_value(w).unwrap_or(Value::Null))
+        .map(|wh| secrets::redacted(wh, cipher))
         .collect();
     let total = items.len();
     Ok(serde_json::json!({ "items": items, "total": total }))
@@ -489,7 +1227,7 @@ async fn resource_list(state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
 async fn resource_get(id: &str, state: &Arc<RwLock<AppState>>) -> Result<Value, String> {
     let st = state.read().await;
     match st.store.get(id) {
-        Some(wh) => Ok(serde_json::to_value(wh).unwrap()),
+        Some(wh) => Ok(secrets::redacted(wh, st.store.cipher()?)),
         None => Err(format!("webhook not found: {id}")),
     }
 }
@@ -505,15 +1243,74 @@ async fn resource_create(data: &Value, state: &Arc<RwLock<AppState>>) -> Result<
         .and_then(|v| v.as_str())
         .ok_or("missing required field: event_type")?
         .to_string();
+    let event_type_template = data
+        .get("event_type_template")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string());
+    if let Some(t) = &event_type_template {
+        EventTypeTemplate::parse(t)?;
+    }
+    let dedupe_key = data
+        .get("dedupe_key")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string());
+    if let Some(source) = &dedupe_key {
+        dedupe::validate_key_source(source)?;
+    }
+    let dedupe_window_seconds = data.get("dedupe_window_seconds").and_then(|v| v.as_u64());
+    let rules = match data.get("rules") {
+        Some(v) if !v.is_null() => rules::parse_rules(v)?,
+        _ => Vec::new(),
+    };
     let verification_method = data
         .get("verification_method")
         .and_then(|v| v.as_str())
         .unwrap_or("none")
         .to_string();
-    let verification_secret = data
-        .get("verification_secret")
+    let secrets = match (data.get("secrets"), data.get("verification_secret")) {
+        (Some(v), _) if !v.is_null() => secrets::parse_secrets(v)?,
+        (_, Some(v)) => v
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(|s| WebhookSecret::new(s.to_string()))
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    };
+    let jwt = match data.get("jwt") {
+        Some(v) if !v.is_null() => Some(JwtConfig::parse(v)?),
+        _ => None,
+    };
+    let timestamp_tolerance_seconds = data
+        .get("timestamp_tolerance_seconds")
+        .and_then(|v| v.as_u64());
+    let challenge_mode = data
+        .get("challenge_mode")
         .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
         .map(|s| s.to_string());
+    let challenge_token = data
+        .get("challenge_token")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string());
+    if let Some(mode) = &challenge_mode {
+        challenge::validate_mode(mode, challenge_token.as_deref())?;
+    }
+    let response = match data.get("response") {
+        Some(v) if !v.is_null() => Some(ResponseTemplate::parse(v)?),
+        _ => None,
+    };
+    let sync_reply = match data.get("sync_reply") {
+        Some(v) if !v.is_null() => Some(SyncReply::parse(v)?),
+        _ => None,
+    };
+    let limits = match data.get("limits") {
+        Some(v) if !v.is_null() => Limits::parse(v)?,
+        _ => Limits::default(),
+    };
 
     let id = format!("wh_{}", &Uuid::new_v4().to_string()[..8]);
     let base_url = {
@@ -526,12 +1323,26 @@ async fn resource_create(data: &Value, state: &Arc<RwLock<AppState>>) -> Result<
         id: id.clone(),
         name,
         event_type,
+        event_type_template,
+        rules,
+        dedupe_key,
+        dedupe_window_seconds,
         verification_method,
-        verification_secret,
+        secrets,
+        verification_secret: None,
+        jwt,
+        timestamp_tolerance_seconds,
+        challenge_mode,
+        challenge_token,
+        sync_reply,
+        response,
+        limits,
         status: "active".to_string(),
         url,
         trigger_count: 0,
         last_triggered: None,
+        failure_count: 0,
+        last_failure: None,
         created_at: Utc::now().to_rfc3339(),
     };
 
@@ -540,7 +1351,7 @@ async fn resource_create(data: &Value, state: &Arc<RwLock<AppState>>) -> Result<
     st.store.save_webhooks().map_err(|e| e.to_string())?;
     st.refresh_webhook_map();
 
-    Ok(serde_json::to_value(&created).unwrap())
+    Ok(secrets::redacted(&created, st.store.cipher()?))
 }
 
 async fn resource_update(
@@ -548,12 +1359,44 @@ async fn resource_update(
     data: Value,
     state: &Arc<RwLock<AppState>>,
 ) -> Result<Value, String> {
+    if let Some(t) = data.get("event_type_template").and_then(|v| v.as_str()) {
+        if !t.is_empty() {
+            EventTypeTemplate::parse(t)?;
+        }
+    }
+    if let Some(v) = data.get("rules").filter(|v| !v.is_null()) {
+        rules::parse_rules(v)?;
+    }
+    if let Some(v) = data.get("limits").filter(|v| !v.is_null()) {
+        Limits::parse(v)?;
+    }
+    if let Some(v) = data.get("jwt").filter(|v| !v.is_null()) {
+        JwtConfig::parse(v)?;
+    }
+    if let Some(source) = data.get("dedupe_key").and_then(|v| v.as_str()) {
+        if !source.is_empty() {
+            dedupe::validate_key_source(source)?;
+        }
+    }
     let mut st = state.write().await;
-    match st.store.update(id, data) {
+    let current = st
+        .store
+        .get(id)
+        .ok_or_else(|| format!("webhook not found: {id}"))?;
+    // The handshake settings are checked together, as they'll be stored.
+    let merged = |field: &str, current: &Option<String>| match data.get(field) {
+        Some(v) => v.as_str().filter(|s| !s.is_empty()).map(String::from),
+        None => current.clone(),
+    };
+    let challenge_token = merged("challenge_token", &current.challenge_token);
+    if let Some(mode) = merged("challenge_mode", &current.challenge_mode) {
+        challenge::validate_mode(&mode, challenge_token.as_deref())?;
+    }
+    match st.store.update(id, data)? {
         Some(updated) => {
             st.store.save_webhooks().map_err(|e| e.to_string())?;
             st.refresh_webhook_map();
-            Ok(serde_json::to_value(&updated).unwrap())
+            Ok(secrets::redacted(&updated, st.store.cipher()?))
         }
         None => Err(format!("webhook not found: {id}")),
     }
@@ -569,3 +1412,293 @@ async fn resource_delete(id: &str, state: &Arc<RwLock<AppState>>) -> Result<Valu
         Err(format!("webhook not found: {id}"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn webhook(id: &str) -> Webhook {
+        serde_json::from_value(json!({
+            "id": id,
+            "name": "test",
+            "event_type": "com.example.test",
+            "verification_method": "none",
+            "status": "active",
+            "url": format!("http://127.0.0.1/hooks/{id}"),
+            "trigger_count": 0,
+            "last_triggered": null,
+            "created_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn delivery(id: &str, webhook_id: &str, received_at: &str, status: &str) -> Delivery {
+        serde_json::from_value(json!({
+            "id": id,
+            "webhook_id": webhook_id,
+            "received_at": received_at,
+            "status": status,
+            "headers": {},
+            "body": { "n": id },
+        }))
+        .unwrap()
+    }
+
+    /// State with an open outbox over a fresh data dir.
+    fn test_state() -> (Arc<RwLock<AppState>>, Arc<Outbox>, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("receiver-test-{}", Uuid::new_v4()));
+        let outbox = Outbox::open(&dir).unwrap();
+        let mut st = AppState::new(dir.clone());
+        st.outbox = Some(outbox.clone());
+        (Arc::new(RwLock::new(st)), outbox, dir)
+    }
+
+    /// Everything queued, in order, emptying the outbox.
+    async fn drain(outbox: &Outbox) -> Vec<outbox::OutboxEntry> {
+        let mut entries = Vec::new();
+        while let Some(entry) = outbox.front().await {
+            outbox.ack(entry.id).await;
+            entries.push(entry);
+        }
+        entries
+    }
+
+    #[tokio::test]
+    async fn replay_goes_through_the_outbox() {
+        let (state, outbox, dir) = test_state();
+        {
+            let mut st = state.write().await;
+            st.store.insert(webhook("wh_a"));
+            st.store.record_delivery(delivery("d1", "wh_a", "2026-03-01T10:00:00Z", "success"));
+        }
+
+        let result = op_replay_delivery(&json!({ "delivery_id": "d1" }), &state).await.unwrap();
+        assert_eq!(result["replayed"], 1);
+        let entries = drain(&outbox).await;
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(result["delivery_ids"][0], entry.delivery.id.as_str());
+        assert_ne!(entry.delivery.id, "d1");
+        assert_eq!(entry.delivery.replayed_from.as_deref(), Some("d1"));
+        assert_eq!(entry.event.replayed_from.as_deref(), Some("d1"));
+        assert_eq!(event_data(entry), json!({ "n": "d1", "replayed_from": "d1" }));
+
+        let err = op_replay_delivery(&json!({ "delivery_id": "missing" }), &state).await;
+        assert_eq!(err.unwrap_err(), "delivery not found: missing");
+        assert!(drain(&outbox).await.is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn replay_range_applies_bounds_and_status() {
+        let (state, outbox, dir) = test_state();
+        {
+            let mut st = state.write().await;
+            st.store.insert(webhook("wh_a"));
+            st.store.insert(webhook("wh_b"));
+            for (id, at, status) in [
+                ("early", "2026-03-01T09:59:59Z", "success"),
+                ("start", "2026-03-01T10:00:00Z", "success"),
+                ("middle", "2026-03-01T10:30:00Z", "success"),
+                ("dropped", "2026-03-01T10:40:00Z", "filtered"),
+                ("end", "2026-03-01T11:00:00Z", "success"),
+                ("late", "2026-03-01T11:00:01Z", "success"),
+            ] {
+                st.store.record_delivery(delivery(id, "wh_a", at, status));
+            }
+            st.store.record_delivery(delivery("other", "wh_b", "2026-03-01T10:30:00Z", "success"));
+        }
+        let range = |extra: Value| {
+            let mut input = json!({
+                "webhook_id": "wh_a",
+                "since": "2026-03-01T10:00:00Z",
+                "until": "2026-03-01T11:00:00Z",
+            });
+            input.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            input
+        };
+        let replayed_from = |entries: Vec<outbox::OutboxEntry>| -> Vec<String> {
+            entries.into_iter().filter_map(|e| e.delivery.replayed_from).collect()
+        };
+
+        // Both bounds are inclusive; oldest first.
+        let result = op_replay_range(&range(json!({})), &state).await.unwrap();
+        assert_eq!(result["replayed"], 3);
+        assert_eq!(replayed_from(drain(&outbox).await), ["start", "middle", "end"]);
+
+        let result = op_replay_range(&range(json!({ "status": "filtered" })), &state).await.unwrap();
+        assert_eq!(result["replayed"], 1);
+        assert_eq!(replayed_from(drain(&outbox).await), ["dropped"]);
+
+        let open_ended = json!({ "webhook_id": "wh_a", "since": "2026-03-01T11:00:00Z" });
+        op_replay_range(&open_ended, &state).await.unwrap();
+        assert_eq!(replayed_from(drain(&outbox).await), ["end", "late"]);
+
+        // A rejected request's body isn't kept, so it can't be replayed.
+        let mut rejected = delivery("rejected", "wh_a", "2026-03-01T10:50:00Z", "rejected");
+        rejected.body = Value::Null;
+        rejected.body_sha256 = Some("00".repeat(32));
+        state.write().await.store.record_delivery(rejected);
+        let err = op_replay_range(&range(json!({ "status": "rejected" })), &state).await;
+        assert!(err.unwrap_err().contains("body was not stored"));
+
+        assert!(op_replay_range(&range(json!({ "since": "yesterday" })), &state).await.is_err());
+        let unknown = json!({ "webhook_id": "wh_missing" });
+        assert_eq!(
+            op_replay_range(&unknown, &state).await.unwrap_err(),
+            "webhook not found: wh_missing"
+        );
+        assert!(drain(&outbox).await.is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// Start a receiver on `dir` in a runtime of its own and stop it again,
+    /// as a restart would. Returns the bound address and the webhook URLs.
+    fn run_once(dir: &Path) -> (SocketAddr, Vec<String>) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let state = Arc::new(RwLock::new(AppState::new(dir.to_path_buf())));
+            let (out, _sent) = mpsc::unbounded_channel();
+            let ipc = Ipc::with_output(out, Duration::from_secs(1));
+            let req = JsonRpcRequest {
+                jsonrpc: "2.0".into(),
+                method: "initialize".into(),
+                params: json!({ "data_dir": dir }),
+                id: json!(1),
+            };
+            let response = handle_initialize(&req, &state, &ipc).await;
+            assert!(response.error.is_none(), "{:?}", response.error.map(|e| e.message));
+            let st = state.read().await;
+            let urls = st.store.list().iter().map(|wh| wh.url.clone()).collect();
+            (st.listen_addr.unwrap(), urls)
+        })
+    }
+
+    fn configure(dir: &Path, f: impl FnOnce(&mut ServerSettings)) {
+        let mut store = store::WebhookStore::new(dir.to_path_buf());
+        store.load().unwrap();
+        f(&mut store.settings);
+        store.save_settings().unwrap();
+    }
+
+    #[test]
+    fn urls_survive_restarts() {
+        let dir = std::env::temp_dir().join(format!("receiver-test-{}", Uuid::new_v4()));
+        let mut store = store::WebhookStore::new(dir.clone());
+        store.insert(webhook("wh_a"));
+        store.save_webhooks().unwrap();
+
+        // The port bound the first time is asked for again.
+        let (first, urls) = run_once(&dir);
+        assert_eq!(urls, [format!("http://127.0.0.1:{}/hooks/wh_a", first.port())]);
+        let (second, again) = run_once(&dir);
+        assert_eq!(second.port(), first.port());
+        assert_eq!(again, urls);
+
+        // If it has been taken meanwhile, another is used and URLs follow.
+        let squatter = std::net::TcpListener::bind(first).unwrap();
+        let (fallback, moved) = run_once(&dir);
+        assert_ne!(fallback.port(), first.port());
+        assert_eq!(moved, [format!("http://127.0.0.1:{}/hooks/wh_a", fallback.port())]);
+        drop(squatter);
+
+        // A configured port wins over the last one.
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        configure(&dir, |settings| settings.port = Some(port));
+        assert_eq!(run_once(&dir).0.port(), port);
+
+        // With a public base URL the port no longer shows in URLs at all.
+        configure(&dir, |settings| {
+            settings.port = None;
+            settings.public_base_url = Some("https://hooks.example.com/".into());
+        });
+        let public = ["https://hooks.example.com/hooks/wh_a".to_string()];
+        assert_eq!(run_once(&dir).1, public);
+        let squatter = std::net::TcpListener::bind(("127.0.0.1", port)).unwrap();
+        assert_eq!(run_once(&dir).1, public);
+        drop(squatter);
+
+        let mut reloaded = store::WebhookStore::new(dir.clone());
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.get("wh_a").unwrap().url, public[0]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn failing_publish_is_given_up_on_without_blocking_others() {
+        let (state, outbox, dir) = test_state();
+        {
+            let mut st = state.write().await;
+            st.store.insert(webhook("wh_a"));
+            st.store.insert(webhook("wh_b"));
+            st.listen_addr = Some("127.0.0.1:8080".parse().unwrap());
+        }
+        let pending = |d: &Delivery| types::PendingEvent {
+            event_type: "com.example.test".into(),
+            subject: d.webhook_id.clone(),
+            data: d.body.clone(),
+            replayed_from: None,
+            correlation_id: None,
+        };
+        for d in [
+            delivery("d1", "wh_a", "2026-03-01T10:00:00Z", "success"),
+            delivery("d2", "wh_b", "2026-03-01T10:01:00Z", "success"),
+        ] {
+            outbox.enqueue(pending(&d), d).await.unwrap();
+        }
+        // d1 is one attempt short of the limit and the host keeps refusing it.
+        let front = outbox.front().await.unwrap();
+        for _ in 1..MAX_PUBLISH_ATTEMPTS {
+            outbox.record_failure(front.id, "bus unavailable".into()).await;
+        }
+        let info = op_get_server_info(&state).await.unwrap();
+        assert_eq!(info["outbox"]["pending_count"], 2);
+        assert_eq!(info["outbox"]["retrying_count"], 1);
+
+        let (out, mut sent) = mpsc::unbounded_channel();
+        let ipc = Ipc::with_output(out, Duration::from_secs(1));
+        let host = ipc.clone();
+        tokio::spawn(async move {
+            while let Some(line) = sent.recv().await {
+                let call: Value = serde_json::from_str(&line).unwrap();
+                let reply = if call["params"]["data"]["n"] == "d1" {
+                    json!({ "id": call["id"], "error": { "message": "schema mismatch" } })
+                } else {
+                    json!({ "id": call["id"], "result": {} })
+                };
+                host.resolve(&reply);
+            }
+        });
+        let publisher = tokio::spawn(publish_loop(state.clone(), ipc, outbox.clone()));
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while outbox.front().await.is_some() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        publisher.abort();
+
+        let st = state.read().await;
+        let failed = st.store.find_delivery("d1").unwrap();
+        assert_eq!(failed.status, "publish_failed");
+        assert_eq!(failed.reason.as_deref(), Some("schema mismatch"));
+        assert_eq!(st.store.find_delivery("d2").unwrap().status, "success");
+        drop(st);
+
+        let info = op_get_server_info(&state).await.unwrap();
+        assert_eq!(info["outbox"]["pending_count"], 0);
+        assert_eq!(info["outbox"]["publish_failed_count"], 1);
+        // The body is kept, so it can be published again once fixed.
+        let result = op_replay_range(
+            &json!({ "webhook_id": "wh_a", "status": "publish_failed" }),
+            &state,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["replayed"], 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,354 @@
+use crate::types::{Delivery, PendingEvent};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{Mutex, Notify};
+
+/// A delivery accepted over HTTP but not yet acknowledged by the host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// Global enqueue order; also the file name on disk.
+    pub id: u64,
+    /// Per-webhook ordering sequence number.
+    pub sequence: u64,
+    pub event: PendingEvent,
+    pub delivery: Delivery,
+    pub attempts: u32,
+    pub enqueued_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+struct OutboxInner {
+    next_id: u64,
+    sequences: HashMap<String, u64>,
+    entries: BTreeMap<u64, OutboxEntry>,
+}
+
+/// Persistent queue of deliveries awaiting publication.
+///
+/// Each entry is written to `outbox/<id>.json` and fsynced before the HTTP
+/// handler answers, so a crash between the 200 and the publish loses nothing:
+/// the entry is picked up again on the next start. Entries are removed only
+/// once the host acknowledges the `event.publish`, which makes delivery
+/// at-least-once.
+pub struct Outbox {
+    dir: PathBuf,
+    inner: Mutex<OutboxInner>,
+    notify: Notify,
+}
+
+impl Outbox {
+    /// Open (or create) the outbox under `data_dir`, loading any entries left
+    /// over from a previous run.
+    pub fn open(data_dir: &Path) -> Result<Arc<Self>, String> {
+        let dir = data_dir.join("outbox");
+        std::fs::create_dir_all(&dir).map_err(|e| format!("failed to create outbox dir: {e}"))?;
+
+        let mut sequences: HashMap<String, u64> =
+            match std::fs::read_to_string(dir.join("sequences.json")) {
+                Ok(content) => serde_json::from_str(&content)
+                    .map_err(|e| format!("failed to parse outbox sequences: {e}"))?,
+                Err(_) => HashMap::new(),
+            };
+
+        let mut entries = BTreeMap::new();
+        let read_dir =
+            std::fs::read_dir(&dir).map_err(|e| format!("failed to read outbox: {e}"))?;
+        for item in read_dir.flatten() {
+            let path = item.path();
+            let is_entry = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .is_some_and(|s| s.parse::<u64>().is_ok());
+            if !is_entry || path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let parsed = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|c| serde_json::from_str::<OutboxEntry>(&c).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(entry) => {
+                    let seq = sequences
+                        .entry(entry.delivery.webhook_id.clone())
+                        .or_default();
+                    *seq = (*seq).max(entry.sequence);
+                    entries.insert(entry.id, entry);
+                }
+                Err(e) => eprintln!(
+                    "webhook-receiver: skipping corrupt outbox entry {}: {e}",
+                    path.display()
+                ),
+            }
+        }
+
+        if !entries.is_empty() {
+            eprintln!(
+                "webhook-receiver: replaying {} undelivered outbox entries",
+                entries.len()
+            );
+        }
+
+        let next_id = entries.keys().next_back().map_or(1, |id| id + 1);
+        let outbox = Arc::new(Self {
+            dir,
+            inner: Mutex::new(OutboxInner {
+                next_id,
+                sequences,
+                entries,
+            }),
+            notify: Notify::new(),
+        });
+        outbox.notify.notify_one();
+        Ok(outbox)
+    }
+
+    /// Durably queue a delivery, assigning its per-webhook sequence number.
+    pub async fn enqueue(
+        &self,
+        event: PendingEvent,
+        mut delivery: Delivery,
+    ) -> Result<OutboxEntry, String> {
+        let mut inner = self.inner.lock().await;
+
+        let seq = inner
+            .sequences
+            .get(&delivery.webhook_id)
+            .copied()
+            .unwrap_or(0)
+            + 1;
+        delivery.sequence = Some(seq);
+        let entry = OutboxEntry {
+            id: inner.next_id,
+            sequence: seq,
+            event,
+            delivery,
+            attempts: 0,
+            enqueued_at: Utc::now(),
+            last_error: None,
+        };
+
+        let mut sequences = inner.sequences.clone();
+        sequences.insert(entry.delivery.webhook_id.clone(), seq);
+        // The lock stays held across the writes so sequences.json never goes
+        // backwards; the fsyncs themselves run off the async workers.
+        let writes = vec![
+            (self.dir.join("sequences.json"), to_json(&sequences)?),
+            (self.entry_path(entry.id), to_json(&entry)?),
+        ];
+        write_durable_blocking(writes).await?;
+
+        inner.sequences = sequences;
+        inner.next_id += 1;
+        inner.entries.insert(entry.id, entry.clone());
+        drop(inner);
+
+        self.notify.notify_one();
+        Ok(entry)
+    }
+
+    /// The oldest unacknowledged entry, if any.
+    pub async fn front(&self) -> Option<OutboxEntry> {
+        self.inner.lock().await.entries.values().next().cloned()
+    }
+
+    /// Wait until something is enqueued.
+    pub async fn wait(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Remove an entry once the host has acknowledged it.
+    pub async fn ack(&self, id: u64) {
+        let mut inner = self.inner.lock().await;
+        inner.entries.remove(&id);
+        let path = self.entry_path(id);
+        let removed = tokio::task::spawn_blocking(move || std::fs::remove_file(path))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r.map_err(|e| e.to_string()));
+        if let Err(e) = removed {
+            eprintln!("webhook-receiver: failed to remove outbox entry {id}: {e}");
+        }
+    }
+
+    /// The pending entry whose delivery matches `f`, if any.
+    pub async fn find(&self, f: impl Fn(&Delivery) -> bool) -> Option<OutboxEntry> {
+        let inner = self.inner.lock().await;
+        inner.entries.values().find(|e| f(&e.delivery)).cloned()
+    }
+
+    /// Record a failed publish attempt so it survives a restart.
+    pub async fn record_failure(&self, id: u64, error: String) -> u32 {
+        let mut inner = self.inner.lock().await;
+        let Some(entry) = inner.entries.get_mut(&id) else {
+            return 0;
+        };
+        entry.attempts += 1;
+        entry.last_error = Some(error);
+        let attempts = entry.attempts;
+        let written = match to_json(&*entry) {
+            Ok(content) => write_durable_blocking(vec![(self.entry_path(id), content)]).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            eprintln!("webhook-receiver: failed to update outbox entry {id}: {e}");
+        }
+        attempts
+    }
+
+    /// Pending entries, how many of them have failed at least once, and the
+    /// last sequence number issued per webhook.
+    pub async fn status(&self) -> serde_json::Value {
+        let inner = self.inner.lock().await;
+        let pending: Vec<serde_json::Value> = inner
+            .entries
+            .values()
+            .map(|e| {
+                serde_json::json!({
+                    "id": e.id,
+                    "webhook_id": e.delivery.webhook_id,
+                    "delivery_id": e.delivery.id,
+                    "sequence": e.sequence,
+                    "attempts": e.attempts,
+                    "enqueued_at": e.enqueued_at,
+                    "last_error": e.last_error,
+                })
+            })
+            .collect();
+        let retrying = inner.entries.values().filter(|e| e.attempts > 0).count();
+        serde_json::json!({
+            "pending_count": pending.len(),
+            "retrying_count": retrying,
+            "pending": pending,
+            "last_sequences": inner.sequences,
+        })
+    }
+
+    fn entry_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{id:020}.json"))
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>, String> {
+    serde_json::to_vec(value).map_err(|e| format!("failed to serialize outbox state: {e}"))
+}
+
+/// Durably write each file in order on the blocking pool.
+async fn write_durable_blocking(writes: Vec<(PathBuf, Vec<u8>)>) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        writes
+            .iter()
+            .try_for_each(|(path, content)| write_durable(path, content))
+    })
+    .await
+    .map_err(|e| format!("outbox write task failed: {e}"))?
+}
+
+/// Write via a temp file, fsync, then rename so readers never see a partial
+/// file and the contents are on disk before we return.
+pub fn write_json_durable<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let content = serde_json::to_vec(value)
+        .map_err(|e| format!("failed to serialize {}: {e}", path.display()))?;
+    write_durable(path, &content)
+}
+
+fn write_durable(path: &Path, content: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension("json.tmp");
+    let mut file = std::fs::File::create(&tmp)
+        .map_err(|e| format!("failed to create {}: {e}", tmp.display()))?;
+    file.write_all(content)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("failed to write {}: {e}", tmp.display()))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("failed to rename {}: {e}", tmp.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pending(webhook_id: &str, n: u64) -> (PendingEvent, Delivery) {
+        let event = PendingEvent {
+            event_type: "test.event".into(),
+            subject: webhook_id.into(),
+            data: json!({ "n": n }),
+            replayed_from: None,
+            correlation_id: None,
+        };
+        let delivery = serde_json::from_value(json!({
+            "id": format!("dlv_{n}"),
+            "webhook_id": webhook_id,
+            "received_at": "2026-03-01T00:00:00Z",
+            "status": "success",
+            "headers": {},
+            "body": { "n": n },
+        }))
+        .unwrap();
+        (event, delivery)
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("outbox-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn enqueue_then_ack_removes_the_entry() {
+        let dir = temp_dir();
+        let outbox = Outbox::open(&dir).unwrap();
+
+        let (event, delivery) = pending("wh_a", 1);
+        let first = outbox.enqueue(event, delivery).await.unwrap();
+        let (event, delivery) = pending("wh_a", 2);
+        let second = outbox.enqueue(event, delivery).await.unwrap();
+        assert_eq!((first.sequence, second.sequence), (1, 2));
+        assert_eq!(second.delivery.sequence, Some(2));
+        assert!(outbox.entry_path(first.id).exists());
+
+        assert_eq!(outbox.front().await.unwrap().id, first.id);
+        outbox.ack(first.id).await;
+        assert!(!outbox.entry_path(first.id).exists());
+        assert_eq!(outbox.front().await.unwrap().id, second.id);
+        outbox.ack(second.id).await;
+        assert!(outbox.front().await.is_none());
+        assert_eq!(outbox.status().await["last_sequences"]["wh_a"], 2);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn unacknowledged_entries_are_replayed_after_restart() {
+        let dir = temp_dir();
+        let outbox = Outbox::open(&dir).unwrap();
+        let mut ids = Vec::new();
+        for (webhook, n) in [("wh_a", 1), ("wh_b", 2), ("wh_a", 3)] {
+            let (event, delivery) = pending(webhook, n);
+            ids.push(outbox.enqueue(event, delivery).await.unwrap().id);
+        }
+        outbox.ack(ids[0]).await;
+        assert_eq!(outbox.record_failure(ids[1], "host gone".into()).await, 1);
+        drop(outbox);
+
+        let reopened = Outbox::open(&dir).unwrap();
+        let front = reopened.front().await.unwrap();
+        assert_eq!(front.id, ids[1]);
+        assert_eq!(front.event.data["n"], 2);
+        assert_eq!(front.attempts, 1);
+        assert_eq!(front.last_error.as_deref(), Some("host gone"));
+        // The outbox wakes its publisher for the leftovers straight away.
+        tokio::time::timeout(std::time::Duration::from_secs(1), reopened.wait())
+            .await
+            .unwrap();
+
+        // Ids and per-webhook sequences continue where they left off.
+        let (event, delivery) = pending("wh_a", 4);
+        let next = reopened.enqueue(event, delivery).await.unwrap();
+        assert!(next.id > ids[2]);
+        assert_eq!(next.sequence, 3);
+        let (event, delivery) = pending("wh_b", 5);
+        assert_eq!(reopened.enqueue(event, delivery).await.unwrap().sequence, 2);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
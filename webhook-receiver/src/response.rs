@@ -0,0 +1,226 @@
+use crate::rules::lookup;
+use crate::types::Delivery;
+use axum::http::{header, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// What to answer an accepted request with, instead of an empty 200.
+/// Also used for filtered and duplicate deliveries, which the sender can't
+/// tell apart from accepted ones.
+///
+/// Header values and the body may contain `{{...}}` placeholders:
+/// `delivery_id`, `webhook_id`, `received_at`, `header:<name>`,
+/// `query:<name>` and `body:<dotted.path>` (the decoded request body).
+/// Unresolved placeholders render empty. Values are escaped for a JSON or
+/// XML body according to the response's `Content-Type`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseTemplate {
+    /// A 2xx status; anything else would make senders retry. Defaults to 200.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Without a `Content-Type` header one is guessed from the body: JSON if
+    /// it starts with `{` or `[`, XML if it starts with `<`, text otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+impl ResponseTemplate {
+    /// Parse `response` from operation input and check it.
+    pub fn parse(value: &Value) -> Result<Self, String> {
+        let template: ResponseTemplate = serde_json::from_value(value.clone())
+            .map_err(|e| format!("invalid response: {e}"))?;
+        if let Some(status) = template.status {
+            if !(200..300).contains(&status) {
+                return Err(format!("invalid response status {status}: must be 2xx"));
+            }
+        }
+        for (name, value) in &template.headers {
+            HeaderName::try_from(name.as_str())
+                .map_err(|_| format!("invalid response header name '{name}'"))?;
+            check_placeholders(value)?;
+        }
+        if let Some(body) = &template.body {
+            check_placeholders(body)?;
+        }
+        Ok(template)
+    }
+
+    pub fn render(&self, delivery: &Delivery) -> Response {
+        let status = self
+            .status
+            .and_then(|s| StatusCode::from_u16(s).ok())
+            .unwrap_or(StatusCode::OK);
+
+        let explicit = self
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.to_ascii_lowercase());
+        let guessed = match &explicit {
+            Some(_) => None,
+            None => self.body.as_deref().map(guess_content_type),
+        };
+        let escape = match explicit.as_deref().or(guessed) {
+            Some(ct) if ct.contains("json") => Escape::Json,
+            Some(ct) if ct.contains("xml") => Escape::Xml,
+            _ => Escape::None,
+        };
+
+        let mut response = match &self.body {
+            Some(body) => (status, render(body, delivery, escape)).into_response(),
+            None => status.into_response(),
+        };
+        let headers = response.headers_mut();
+        if let Some(ct) = guessed {
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(ct));
+        }
+        for (name, value) in &self.headers {
+            let Ok(name) = HeaderName::try_from(name.as_str()) else {
+                continue;
+            };
+            // An echoed value with a newline in it is dropped, not sent.
+            if let Ok(value) = HeaderValue::from_str(&render(value, delivery, Escape::None)) {
+                headers.insert(name, value);
+            }
+        }
+        response
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Escape {
+    None,
+    Json,
+    Xml,
+}
+
+fn guess_content_type(body: &str) -> &'static str {
+    match body.trim_start().chars().next() {
+        Some('{' | '[') => "application/json",
+        Some('<') => "application/xml",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+fn check_placeholders(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        let close = rest[open..]
+            .find("}}")
+            .ok_or_else(|| format!("unclosed '{{{{' in response template: {template}"))?;
+        let name = rest[open + 2..open + close].trim();
+        match name.split_once(':') {
+            None if matches!(name, "delivery_id" | "webhook_id" | "received_at") => {}
+            Some(("header" | "query", n)) if !n.is_empty() => {}
+            Some(("body", _)) => {}
+            _ => return Err(format!("unknown placeholder '{{{{{name}}}}}' in response template")),
+        }
+        rest = &rest[open + close + 2..];
+    }
+    Ok(())
+}
+
+fn render(template: &str, delivery: &Delivery, escape: Escape) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        let Some(close) = rest[open..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..open]);
+        let value = resolve(rest[open + 2..open + close].trim(), delivery).unwrap_or_default();
+        match escape {
+            Escape::None => out.push_str(&value),
+            Escape::Json => {
+                let quoted = Value::String(value).to_string();
+                out.push_str(&quoted[1..quoted.len() - 1]);
+            }
+            Escape::Xml => {
+                for c in value.chars() {
+                    match c {
+                        '&' => out.push_str("&amp;"),
+                        '<' => out.push_str("&lt;"),
+                        '>' => out.push_str("&gt;"),
+                        '"' => out.push_str("&quot;"),
+                        '\'' => out.push_str("&apos;"),
+                        c => out.push(c),
+                    }
+                }
+            }
+        }
+        rest = &rest[open + close + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn resolve(name: &str, delivery: &Delivery) -> Option<String> {
+    let value = match name.split_once(':') {
+        None => match name {
+            "delivery_id" => return Some(delivery.id.clone()),
+            "webhook_id" => return Some(delivery.webhook_id.clone()),
+            "received_at" => return Some(delivery.received_at.to_rfc3339()),
+            _ => return None,
+        },
+        Some(("header", n)) => delivery.headers.get(n.to_ascii_lowercase())?,
+        Some(("query", n)) => delivery.query.as_ref()?.get(n)?,
+        Some(("body", path)) => lookup(&delivery.body, path)?,
+        _ => return None,
+    };
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivery() -> Delivery {
+        Delivery {
+            id: "d1".into(),
+            webhook_id: "wh_1".into(),
+            received_at: chrono::Utc::now(),
+            status: "success".into(),
+            headers: serde_json::json!({ "x-request-id": "r\"1" }),
+            body: serde_json::json!({ "user": { "name": "<Ann>" } }),
+            query: None,
+            sequence: None,
+            replayed_from: None,
+            reason: None,
+            body_sha256: None,
+            source_addr: None,
+            secret_id: None,
+            claims: None,
+        }
+    }
+
+    #[test]
+    fn escapes_for_content_type() {
+        let d = delivery();
+        assert_eq!(
+            render(r#"{"id":"{{delivery_id}}","req":"{{header:X-Request-Id}}"}"#, &d, Escape::Json),
+            r#"{"id":"d1","req":"r\"1"}"#
+        );
+        assert_eq!(
+            render("<Message>Hi {{body:user.name}}{{query:missing}}</Message>", &d, Escape::Xml),
+            "<Message>Hi &lt;Ann&gt;</Message>"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let parse = |v| ResponseTemplate::parse(&v);
+        assert!(parse(serde_json::json!({ "status": 202, "body": "{\"ok\":true}" })).is_ok());
+        assert!(parse(serde_json::json!({ "status": 500 })).is_err());
+        assert!(parse(serde_json::json!({ "body": "{{nope}}" })).is_err());
+        assert!(parse(serde_json::json!({ "body": "{{delivery_id" })).is_err());
+        assert!(parse(serde_json::json!({ "headers": { "bad header": "x" } })).is_err());
+    }
+}
@@ -0,0 +1,427 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// A per-webhook transformation or filter, applied in order before a
+/// delivery is published.
+///
+/// Field references (`field`, `from` and the values of `fields`) read the
+/// original request: `header:<name>` or `body:<dotted.path>`, where numeric
+/// segments index arrays and `body:` alone is the whole body. Targets
+/// (`to`, `field` in `set`, and the keys of `fields`) are dotted paths into
+/// the event data being built, which starts out as the request body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Rule {
+    /// Drop the delivery if every condition matches.
+    Drop {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        when: Vec<Condition>,
+    },
+    /// Replace the event data with an object built from the given
+    /// references, keyed by output path.
+    Project { fields: BTreeMap<String, String> },
+    /// Move a field within the event data.
+    Rename { from: String, to: String },
+    /// Set a field in the event data from a reference or a constant.
+    Set {
+        field: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        from: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<Value>,
+    },
+}
+
+/// A predicate on one field. Every operator given must hold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Condition {
+    pub field: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equals: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_equals: Option<Value>,
+    #[serde(default, rename = "in", skip_serializing_if = "Option::is_none")]
+    pub one_of: Option<Vec<Value>>,
+    /// Substring match on string values.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contains: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exists: Option<bool>,
+}
+
+/// Parse and check a `rules` array from operation input.
+pub fn parse_rules(value: &Value) -> Result<Vec<Rule>, String> {
+    let rules: Vec<Rule> =
+        serde_json::from_value(value.clone()).map_err(|e| format!("invalid rules: {e}"))?;
+    for (i, rule) in rules.iter().enumerate() {
+        validate(rule).map_err(|e| format!("invalid rule {i}: {e}"))?;
+    }
+    Ok(rules)
+}
+
+fn validate(rule: &Rule) -> Result<(), String> {
+    match rule {
+        Rule::Drop { when, .. } => {
+            if when.is_empty() {
+                return Err("drop needs at least one condition".into());
+            }
+            for c in when {
+                check_reference(&c.field)?;
+                if c.equals.is_none()
+                    && c.not_equals.is_none()
+                    && c.one_of.is_none()
+                    && c.contains.is_none()
+                    && c.exists.is_none()
+                {
+                    return Err(format!(
+                        "condition on '{}' needs one of equals, not_equals, in, contains, exists",
+                        c.field
+                    ));
+                }
+            }
+        }
+        Rule::Project { fields } => {
+            if fields.is_empty() {
+                return Err("project needs at least one field".into());
+            }
+            for (target, source) in fields {
+                check_target(target)?;
+                check_reference(source)?;
+            }
+        }
+        Rule::Rename { from, to } => {
+            check_target(from)?;
+            check_target(to)?;
+        }
+        Rule::Set { field, from, value } => {
+            check_target(field)?;
+            match (from, value) {
+                (Some(from), None) => check_reference(from)?,
+                (None, Some(_)) => {}
+                _ => return Err("set needs exactly one of 'from' or 'value'".into()),
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn check_reference(reference: &str) -> Result<(), String> {
+    match reference.split_once(':') {
+        Some(("header", name)) if !name.is_empty() => Ok(()),
+        Some(("body", _)) => Ok(()),
+        _ => Err(format!(
+            "invalid field reference '{reference}': expected header:<name> or body:<path>"
+        )),
+    }
+}
+
+fn check_target(path: &str) -> Result<(), String> {
+    if path.is_empty() || path.split('.').any(|s| s.is_empty()) {
+        return Err(format!("invalid field path '{path}'"));
+    }
+    Ok(())
+}
+
+/// What to do with a delivery after running its webhook's rules.
+pub enum Outcome {
+    Publish(Value),
+    /// Dropped; carries the reason recorded on the delivery.
+    Drop(String),
+}
+
+/// Run `rules` over a delivery's headers and body.
+pub fn apply(rules: &[Rule], headers: &Value, body: &Value) -> Outcome {
+    let mut data = body.clone();
+    for (i, rule) in rules.iter().enumerate() {
+        match rule {
+            Rule::Drop { name, when } => {
+                if when.iter().all(|c| matches(c, headers, body)) {
+                    let reason = match name {
+                        Some(name) => format!("rule '{name}'"),
+                        None => format!("rule {i}"),
+                    };
+                    return Outcome::Drop(reason);
+                }
+            }
+            Rule::Project { fields } => {
+                let mut projected = Value::Object(Map::new());
+                for (target, source) in fields {
+                    if let Some(v) = resolve(source, headers, body) {
+                        insert(&mut projected, target, v);
+                    }
+                }
+                data = projected;
+            }
+            Rule::Rename { from, to } => {
+                if let Some(v) = remove(&mut data, from) {
+                    insert(&mut data, to, v);
+                }
+            }
+            Rule::Set { field, from, value } => {
+                let v = match (from, value) {
+                    (Some(from), _) => resolve(from, headers, body),
+                    (None, v) => v.clone(),
+                };
+                if let Some(v) = v {
+                    insert(&mut data, field, v);
+                }
+            }
+        }
+    }
+    Outcome::Publish(data)
+}
+
+fn matches(c: &Condition, headers: &Value, body: &Value) -> bool {
+    let value = resolve(&c.field, headers, body);
+    if let Some(exists) = c.exists {
+        if value.is_some() != exists {
+            return false;
+        }
+    }
+    if let Some(expected) = &c.equals {
+        if value.as_ref() != Some(expected) {
+            return false;
+        }
+    }
+    if let Some(unexpected) = &c.not_equals {
+        if value.as_ref() == Some(unexpected) {
+            return false;
+        }
+    }
+    if let Some(options) = &c.one_of {
+        if !value.as_ref().is_some_and(|v| options.contains(v)) {
+            return false;
+        }
+    }
+    if let Some(needle) = &c.contains {
+        if !value
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .is_some_and(|s| s.contains(needle.as_str()))
+        {
+            return false;
+        }
+    }
+    true
+}
+
+/// Resolve a `header:` or `body:` reference. Missing fields and JSON nulls
+/// both resolve to `None`.
+pub fn resolve(reference: &str, headers: &Value, body: &Value) -> Option<Value> {
+    let found = match reference.split_once(':')? {
+        ("header", name) => headers.get(name.to_ascii_lowercase())?,
+        ("body", path) => lookup(body, path)?,
+        _ => return None,
+    };
+    (!found.is_null()).then(|| found.clone())
+}
+
+/// Walk a dotted path into `value`; numeric segments index arrays. An empty
+/// path is `value` itself.
+pub fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    path.split('.').try_fold(value, |current, segment| match current {
+        Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+        other => other.get(segment),
+    })
+}
+
+/// Set a dotted path in `data`, creating intermediate objects. Does nothing
+/// if the path runs through a non-object value such as a raw string body.
+fn insert(data: &mut Value, path: &str, value: Value) {
+    let mut segments = path.split('.').peekable();
+    let mut current = data;
+    while let Some(segment) = segments.next() {
+        if current.is_null() {
+            *current = Value::Object(Map::new());
+        }
+        let Value::Object(map) = current else {
+            return;
+        };
+        if segments.peek().is_none() {
+            map.insert(segment.to_string(), value);
+            return;
+        }
+        current = map.entry(segment.to_string()).or_insert(Value::Null);
+    }
+}
+
+/// Remove and return the value at a dotted path in `data`.
+fn remove(data: &mut Value, path: &str) -> Option<Value> {
+    let (parent, last) = match path.rsplit_once('.') {
+        Some((parent, last)) => (lookup_mut(data, parent)?, last),
+        None => (data, path),
+    };
+    parent.as_object_mut()?.remove(last)
+}
+
+fn lookup_mut<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.').try_fold(value, |current, segment| match current {
+        Value::Array(items) => items.get_mut(segment.parse::<usize>().ok()?),
+        other => other.get_mut(segment),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(value: Value) -> Vec<Rule> {
+        parse_rules(&value).unwrap()
+    }
+
+    fn dropped(rules: &[Rule], headers: &Value, body: &Value) -> Option<String> {
+        match apply(rules, headers, body) {
+            Outcome::Drop(reason) => Some(reason),
+            Outcome::Publish(_) => None,
+        }
+    }
+
+    fn published(rules: &[Rule], headers: &Value, body: &Value) -> Value {
+        match apply(rules, headers, body) {
+            Outcome::Publish(data) => data,
+            Outcome::Drop(reason) => panic!("dropped by {reason}"),
+        }
+    }
+
+    fn drop_when(condition: Value) -> Vec<Rule> {
+        rules(json!([{ "action": "drop", "when": [condition] }]))
+    }
+
+    #[test]
+    fn condition_operators() {
+        let headers = json!({ "x-github-event": "push" });
+        let body = json!({ "ref": "refs/heads/main", "n": 3, "draft": null, "tags": ["a"] });
+        let cases = [
+            (json!({ "field": "header:X-GitHub-Event", "equals": "push" }), true),
+            (json!({ "field": "body:n", "equals": 3 }), true),
+            (json!({ "field": "body:n", "equals": "3" }), false),
+            (json!({ "field": "body:n", "not_equals": 4 }), true),
+            (json!({ "field": "body:n", "not_equals": 3 }), false),
+            (json!({ "field": "body:n", "in": [1, 2, 3] }), true),
+            (json!({ "field": "body:n", "in": [1, 2] }), false),
+            (json!({ "field": "body:ref", "contains": "heads/" }), true),
+            (json!({ "field": "body:ref", "contains": "tags/" }), false),
+            (json!({ "field": "body:n", "contains": "3" }), false),
+            (json!({ "field": "body:tags.0", "exists": true }), true),
+            (json!({ "field": "body:tags.1", "exists": true }), false),
+            // Null counts as missing.
+            (json!({ "field": "body:draft", "exists": false }), true),
+        ];
+        for (condition, drops) in cases {
+            let rules = drop_when(condition.clone());
+            assert_eq!(dropped(&rules, &headers, &body).is_some(), drops, "{condition}");
+        }
+    }
+
+    #[test]
+    fn missing_fields_only_match_negative_operators() {
+        let (headers, body) = (json!({}), json!({ "a": { "b": 1 } }));
+        for condition in [
+            json!({ "field": "body:a.c", "equals": 1 }),
+            json!({ "field": "body:a.b.c", "in": [1] }),
+            json!({ "field": "header:x-missing", "contains": "" }),
+            json!({ "field": "body:a.b.0", "exists": true }),
+        ] {
+            let rules = drop_when(condition.clone());
+            assert_eq!(dropped(&rules, &headers, &body), None, "{condition}");
+        }
+        for condition in [
+            json!({ "field": "body:a.c", "not_equals": 1 }),
+            json!({ "field": "header:x-missing", "exists": false }),
+        ] {
+            let rules = drop_when(condition.clone());
+            assert!(dropped(&rules, &headers, &body).is_some(), "{condition}");
+        }
+    }
+
+    #[test]
+    fn drop_needs_every_condition_and_names_the_rule() {
+        let rules = rules(json!([
+            { "action": "drop", "name": "bots", "when": [
+                { "field": "body:sender.type", "equals": "Bot" },
+                { "field": "body:action", "in": ["opened", "edited"] },
+            ] },
+            { "action": "drop", "when": [{ "field": "body:action", "equals": "closed" }] },
+        ]));
+        let bot = |action: &str| json!({ "sender": { "type": "Bot" }, "action": action });
+        assert_eq!(dropped(&rules, &json!({}), &bot("opened")).as_deref(), Some("rule 'bots'"));
+        assert_eq!(dropped(&rules, &json!({}), &bot("labeled")), None);
+        assert_eq!(dropped(&rules, &json!({}), &bot("closed")).as_deref(), Some("rule 1"));
+    }
+
+    #[test]
+    fn transformations_build_the_event_data() {
+        let headers = json!({ "x-github-event": "push" });
+        let body = json!({ "repository": { "full_name": "o/r" }, "commits": [{ "id": "c1" }] });
+
+        let projected = published(
+            &rules(json!([{ "action": "project", "fields": {
+                "repo": "body:repository.full_name",
+                "event.kind": "header:x-github-event",
+                "first": "body:commits.0.id",
+                "missing": "body:nope",
+            } }])),
+            &headers,
+            &body,
+        );
+        assert_eq!(
+            projected,
+            json!({ "repo": "o/r", "event": { "kind": "push" }, "first": "c1" })
+        );
+
+        // References read the original request even after a projection.
+        let data = published(
+            &rules(json!([
+                { "action": "project", "fields": { "repo": "body:repository.full_name" } },
+                { "action": "rename", "from": "repo", "to": "meta.repository" },
+                { "action": "set", "field": "meta.event", "from": "header:x-github-event" },
+                { "action": "set", "field": "source", "value": "github" },
+                { "action": "rename", "from": "absent", "to": "ignored" },
+            ])),
+            &headers,
+            &body,
+        );
+        assert_eq!(
+            data,
+            json!({ "meta": { "repository": "o/r", "event": "push" }, "source": "github" })
+        );
+
+        // Paths through non-objects are left alone.
+        let raw = json!("plain text");
+        let rules = rules(json!([{ "action": "set", "field": "a", "value": 1 }]));
+        assert_eq!(published(&rules, &json!({}), &raw), raw);
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        for value in [
+            json!([{ "action": "drop", "when": [] }]),
+            json!([{ "action": "drop", "when": [{ "field": "body:a" }] }]),
+            json!([{ "action": "drop", "when": [{ "field": "query:a", "exists": true }] }]),
+            json!([{ "action": "project", "fields": {} }]),
+            json!([{ "action": "rename", "from": "a..b", "to": "c" }]),
+            json!([{ "action": "set", "field": "a" }]),
+            json!([{ "action": "set", "field": "a", "from": "body:b", "value": 1 }]),
+            json!([{ "action": "explode" }]),
+        ] {
+            assert!(parse_rules(&value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn lookup_paths() {
+        let body = json!({ "a": [{ "b": "x" }], "n": null });
+        assert_eq!(lookup(&body, ""), Some(&body));
+        assert_eq!(lookup(&body, "a.0.b"), Some(&json!("x")));
+        assert_eq!(lookup(&body, "a.b"), None);
+        assert_eq!(lookup(&body, "a.1"), None);
+        assert_eq!(resolve("body:n", &json!({}), &body), None);
+        assert_eq!(resolve("body:", &json!({}), &body), Some(body.clone()));
+    }
+}
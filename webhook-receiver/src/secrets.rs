@@ -0,0 +1,399 @@
+use crate::types::Webhook;
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+/// How long the previous secrets stay valid after `rotate_secret` when the
+/// caller doesn't say: long enough to update the sender's configuration.
+pub const DEFAULT_OVERLAP_SECS: u64 = 86_400;
+
+/// Environment variable that supplies the encryption key (base64, 32
+/// bytes) instead of the key file.
+const KEY_ENV: &str = "WEBHOOK_RECEIVER_SECRET_KEY";
+
+/// Environment variable naming the key file, to keep it off the data dir.
+const KEY_FILE_ENV: &str = "WEBHOOK_RECEIVER_SECRET_KEY_FILE";
+
+/// Default key file, next to but separate from `webhooks.json`.
+const KEY_FILE: &str = "secret.key";
+
+/// Label the fingerprint key is derived under, so it is never the
+/// encryption key itself.
+const FINGERPRINT_LABEL: &[u8] = b"webhook-receiver secret fingerprint v1";
+
+/// Prefix of an encrypted value as stored in `webhooks.json`.
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Encrypts secrets at rest with ChaCha20-Poly1305. The key comes from
+/// `WEBHOOK_RECEIVER_SECRET_KEY`, or from the file named by
+/// `WEBHOOK_RECEIVER_SECRET_KEY_FILE`, or else from `secret.key` in the data
+/// dir, generated (mode 0600) on first use.
+///
+/// The default file sits in the same directory as `webhooks.json`, so a
+/// copy of the data dir carries both the ciphertext and its key; the
+/// encryption then only keeps secrets out of anything that reads
+/// `webhooks.json` alone. Supply the key through the environment, or keep
+/// the file elsewhere, where backups of the data dir must not be able to
+/// decrypt them.
+pub struct Cipher {
+    key: LessSafeKey,
+    fingerprint_key: hmac::Key,
+}
+
+impl Cipher {
+    pub fn open(data_dir: &Path) -> Result<Self, String> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let key = match std::env::var(KEY_ENV) {
+            Ok(encoded) => engine
+                .decode(encoded.trim())
+                .map_err(|e| format!("invalid {KEY_ENV}: {e}"))?,
+            Err(_) => {
+                let path = std::env::var_os(KEY_FILE_ENV)
+                    .map(std::path::PathBuf::from)
+                    .unwrap_or_else(|| data_dir.join(KEY_FILE));
+                match std::fs::read_to_string(&path) {
+                    Ok(encoded) => engine
+                        .decode(encoded.trim())
+                        .map_err(|e| format!("invalid {}: {e}", path.display()))?,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        let mut key = vec![0u8; 32];
+                        getrandom::getrandom(&mut key)
+                            .map_err(|e| format!("failed to generate secret key: {e}"))?;
+                        write_key_file(&path, &engine.encode(&key))?;
+                        if path.starts_with(data_dir) {
+                            eprintln!(
+                                "webhook-receiver: generated {}; it decrypts the secrets \
+                                 in the same directory, set {KEY_ENV} or {KEY_FILE_ENV} \
+                                 to keep it apart",
+                                path.display()
+                            );
+                        }
+                        key
+                    }
+                    Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+                }
+            }
+        };
+        let derived = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), FINGERPRINT_LABEL);
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+            .map_err(|_| "secret key must be 32 bytes".to_string())?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            fingerprint_key: hmac::Key::new(hmac::HMAC_SHA256, derived.as_ref()),
+        })
+    }
+
+    /// A short, stable identifier for a secret that doesn't reveal it. It
+    /// is keyed, so a low-entropy secret can't be recovered by hashing
+    /// guesses without the key as well.
+    pub fn fingerprint(&self, value: &str) -> String {
+        let tag = hmac::sign(&self.fingerprint_key, value.as_bytes());
+        format!("hmac-sha256:{}", &hex::encode(tag.as_ref())[..16])
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).map_err(|e| format!("failed to generate nonce: {e}"))?;
+        let mut data = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| "failed to encrypt secret".to_string())?;
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&data);
+        Ok(format!(
+            "{ENCRYPTED_PREFIX}{}",
+            base64::engine::general_purpose::STANDARD.encode(out)
+        ))
+    }
+
+    /// Decrypt a stored value. Values without the prefix were written
+    /// before encryption and are returned as they are.
+    pub fn decrypt(&self, stored: &str) -> Result<String, String> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let mut data = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|_| "malformed encrypted secret".to_string())?;
+        if data.len() < NONCE_LEN {
+            return Err("malformed encrypted secret".into());
+        }
+        let nonce = Nonce::try_assume_unique_for_key(&data[..NONCE_LEN])
+            .map_err(|_| "malformed encrypted secret".to_string())?;
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut data[NONCE_LEN..])
+            .map_err(|_| "failed to decrypt secret: wrong key or corrupt data".to_string())?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| "decrypted secret is not UTF-8".into())
+    }
+}
+
+fn write_key_file(path: &Path, contents: &str) -> Result<(), String> {
+    use std::io::Write;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("failed to create data dir: {e}"))?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("failed to create {}: {e}", path.display()))?;
+    file.write_all(contents.as_bytes())
+        .and_then(|()| file.sync_all())
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+/// A webhook as returned by operations and resources: secret values and the
+/// challenge token are replaced by their fingerprints.
+pub fn redacted(wh: &Webhook, cipher: &Cipher) -> Value {
+    let now = Utc::now();
+    let mut value = serde_json::to_value(wh).unwrap_or(Value::Null);
+    value["secrets"] = wh
+        .secrets
+        .iter()
+        .map(|secret| {
+            let mut entry = serde_json::to_value(secret).unwrap_or(Value::Null);
+            if let Some(entry) = entry.as_object_mut() {
+                entry.remove("value");
+                entry.insert("fingerprint".into(), cipher.fingerprint(&secret.value).into());
+                entry.insert("active".into(), secret.is_active(now).into());
+            }
+            entry
+        })
+        .collect();
+    if let (Some(obj), Some(token)) = (value.as_object_mut(), &wh.challenge_token) {
+        obj.remove("challenge_token");
+        obj.insert("challenge_token_fingerprint".into(), cipher.fingerprint(token).into());
+    }
+    value
+}
+
+/// One of a webhook's verification secrets. A request passes if it verifies
+/// against any secret that is active when it arrives.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSecret {
+    pub id: String,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebhookSecret {
+    pub fn new(value: String) -> Self {
+        Self {
+            id: new_id(),
+            value,
+            not_before: None,
+            expires_at: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.not_before.is_none_or(|t| t <= now) && self.expires_at.is_none_or(|t| now < t)
+    }
+}
+
+fn new_id() -> String {
+    format!("sec_{}", &uuid::Uuid::new_v4().simple().to_string()[..8])
+}
+
+/// Parse a `secrets` array from operation input. Entries are
+/// `{value, not_before?, expires_at?, id?}`; missing ids are generated.
+pub fn parse_secrets(value: &Value) -> Result<Vec<WebhookSecret>, String> {
+    #[derive(Deserialize)]
+    struct Input {
+        #[serde(default)]
+        id: Option<String>,
+        value: String,
+        #[serde(default)]
+        not_before: Option<DateTime<Utc>>,
+        #[serde(default)]
+        expires_at: Option<DateTime<Utc>>,
+    }
+
+    let inputs: Vec<Input> =
+        serde_json::from_value(value.clone()).map_err(|e| format!("invalid secrets: {e}"))?;
+    let mut secrets: Vec<WebhookSecret> = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        if input.value.is_empty() {
+            return Err("invalid secrets: value must not be empty".into());
+        }
+        if let (Some(start), Some(end)) = (input.not_before, input.expires_at) {
+            if end <= start {
+                return Err("invalid secrets: expires_at must be after not_before".into());
+            }
+        }
+        let id = input.id.filter(|id| !id.is_empty()).unwrap_or_else(new_id);
+        if secrets.iter().any(|s| s.id == id) {
+            return Err(format!("invalid secrets: duplicate id '{id}'"));
+        }
+        secrets.push(WebhookSecret {
+            id,
+            value: input.value,
+            not_before: input.not_before,
+            expires_at: input.expires_at,
+            created_at: Utc::now(),
+        });
+    }
+    Ok(secrets)
+}
+
+/// Make the secrets active at `now` expire by `expires_at` at the latest, for
+/// a rotation. Secrets scheduled to start later keep their own window, and
+/// ones that have already expired are dropped.
+pub fn retire_active(
+    secrets: &mut Vec<WebhookSecret>,
+    expires_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) {
+    for secret in secrets.iter_mut() {
+        if secret.is_active(now) && secret.expires_at.is_none_or(|t| t > expires_at) {
+            secret.expires_at = Some(expires_at);
+        }
+    }
+    secrets.retain(|s| s.expires_at.is_none_or(|t| t > now));
+}
+
+/// Generate a strong secret in the form `method` expects. Custom-header
+/// secrets keep the header name of `current`, and basic-auth ones its user.
+pub fn generate(method: &str, current: Option<&str>) -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("failed to generate secret: {e}"))?;
+    Ok(match method {
+        "standard-webhooks" => format!(
+            "whsec_{}",
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        ),
+        "custom-header" => {
+            let header = current
+                .and_then(|s| s.split_once(':'))
+                .map(|(name, _)| name)
+                .ok_or("custom-header rotation needs an existing 'Header-Name:value' secret")?;
+            format!("{header}:{}", hex::encode(bytes))
+        }
+        "basic" => {
+            let user = current
+                .and_then(|s| s.split_once(':'))
+                .map(|(user, _)| user)
+                .ok_or("basic rotation needs an existing 'user:password' secret")?;
+            format!("{user}:{}", hex::encode(bytes))
+        }
+        "none" => return Err("webhook has no verification method to rotate a secret for".into()),
+        // Stripe, Slack, Shopify and Twilio issue their own secrets; a
+        // generated one is only useful where the sender accepts any value.
+        _ => hex::encode(bytes),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cipher_round_trip() {
+        let dir = std::env::temp_dir().join(format!("wr-secrets-{}", uuid::Uuid::new_v4()));
+        let cipher = Cipher::open(&dir).unwrap();
+        let stored = cipher.encrypt("whsec_abc").unwrap();
+        assert!(stored.starts_with(ENCRYPTED_PREFIX));
+        assert_ne!(stored, cipher.encrypt("whsec_abc").unwrap());
+        // The key file is reused on the next open.
+        assert_eq!(Cipher::open(&dir).unwrap().decrypt(&stored).unwrap(), "whsec_abc");
+        assert_eq!(cipher.decrypt("plain").unwrap(), "plain");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fingerprints_are_keyed() {
+        let dir = std::env::temp_dir().join(format!("wr-secrets-{}", uuid::Uuid::new_v4()));
+        let other = std::env::temp_dir().join(format!("wr-secrets-{}", uuid::Uuid::new_v4()));
+        let cipher = Cipher::open(&dir).unwrap();
+        let fp = cipher.fingerprint("hunter2");
+        assert!(fp.starts_with("hmac-sha256:"));
+        assert_eq!(fp.len(), "hmac-sha256:".len() + 16);
+        assert_ne!(fp, cipher.fingerprint("hunter3"));
+        // Stable under the same key, unrelated under another one.
+        assert_eq!(Cipher::open(&dir).unwrap().fingerprint("hunter2"), fp);
+        assert_ne!(Cipher::open(&other).unwrap().fingerprint("hunter2"), fp);
+        // Not the plain digest a guess could be checked against.
+        let plain = hex::encode(ring::digest::digest(&ring::digest::SHA256, b"hunter2"));
+        assert!(!fp.ends_with(&plain[..16]));
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_dir_all(&other);
+    }
+
+    #[test]
+    fn active_window() {
+        let now = Utc::now();
+        let mut secret = WebhookSecret::new("s".into());
+        assert!(secret.is_active(now));
+        secret.not_before = Some(now + chrono::Duration::seconds(10));
+        assert!(!secret.is_active(now));
+        secret.not_before = None;
+        secret.expires_at = Some(now);
+        assert!(!secret.is_active(now));
+    }
+
+    #[test]
+    fn rotation_caps_only_active_secrets() {
+        let now = Utc::now();
+        let hours = |h| now + chrono::Duration::hours(h);
+        let mut secrets = parse_secrets(&serde_json::json!([
+            { "id": "current", "value": "a" },
+            { "id": "ending", "value": "b", "expires_at": hours(2) },
+            { "id": "next", "value": "c", "not_before": hours(48), "expires_at": hours(96) },
+            { "id": "later", "value": "d", "not_before": hours(72) },
+            { "id": "gone", "value": "e", "not_before": hours(-2), "expires_at": hours(-1) },
+        ]))
+        .unwrap();
+        retire_active(&mut secrets, hours(24), now);
+
+        let expiry = |id: &str| secrets.iter().find(|s| s.id == id).map(|s| s.expires_at);
+        assert_eq!(expiry("current"), Some(Some(hours(24))));
+        assert_eq!(expiry("ending"), Some(Some(hours(2))));
+        assert_eq!(expiry("next"), Some(Some(hours(96))));
+        assert_eq!(expiry("later"), Some(None));
+        assert_eq!(expiry("gone"), None);
+    }
+
+    #[test]
+    fn generated_formats() {
+        let sw = generate("standard-webhooks", None).unwrap();
+        assert!(sw.starts_with("whsec_"));
+        assert_ne!(sw, generate("standard-webhooks", None).unwrap());
+        assert_eq!(generate("github-hmac", None).unwrap().len(), 64);
+        assert!(generate("custom-header", Some("X-Token:old"))
+            .unwrap()
+            .starts_with("X-Token:"));
+        assert!(generate("custom-header", None).is_err());
+        assert!(generate("none", None).is_err());
+    }
+
+    #[test]
+    fn parse_checks_entries() {
+        let parsed = parse_secrets(&serde_json::json!([
+            { "value": "a" },
+            { "id": "next", "value": "b", "not_before": "2030-01-01T00:00:00Z" },
+        ]))
+        .unwrap();
+        assert!(parsed[0].id.starts_with("sec_"));
+        assert_eq!(parsed[1].id, "next");
+        assert!(parse_secrets(&serde_json::json!([{ "value": "" }])).is_err());
+        assert!(parse_secrets(&serde_json::json!([
+            { "value": "a", "not_before": "2030-01-02T00:00:00Z", "expires_at": "2030-01-01T00:00:00Z" }
+        ]))
+        .is_err());
+    }
+}
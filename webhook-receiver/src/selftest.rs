@@ -0,0 +1,351 @@
+use crate::tls::{self, CertResolver};
+use crate::types::Webhook;
+use axum::body::Bytes;
+use axum::http::{header, Method, Request};
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper_util::rt::TokioIo;
+use serde_json::Value;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+
+/// Header that marks a test request, so its delivery can be found again.
+pub const TEST_ID_HEADER: &str = "x-webhook-receiver-test";
+
+/// How much of the listener's response body is reported.
+const MAX_RESPONSE_BYTES: usize = 64 * 1024;
+
+/// The payload sent when the caller doesn't supply one.
+pub fn sample_body(wh: &Webhook, test_id: &str, now: DateTime<Utc>) -> Value {
+    serde_json::json!({
+        "type": "webhook_receiver.test",
+        "test_id": test_id,
+        "webhook_id": wh.id,
+        "sent_at": now.to_rfc3339(),
+        "message": "Test delivery from send_test_delivery",
+    })
+}
+
+/// Headers and query string that make a request pass the webhook's
+/// verification, or why none could be produced.
+#[derive(Debug, Default)]
+pub struct Signed {
+    pub headers: Vec<(String, String)>,
+    pub query: Option<String>,
+    pub unsigned_reason: Option<String>,
+}
+
+/// Sign a request the way the webhook's verification method expects, with
+/// its newest active secret.
+pub fn sign(wh: &Webhook, body: &[u8], content_type: &str, now: DateTime<Utc>) -> Signed {
+    let secret = wh
+        .secrets
+        .iter()
+        .filter(|s| s.is_active(now))
+        .max_by_key(|s| s.created_at)
+        .map(|s| s.value.as_str());
+    let secret = match (wh.verification_method.as_str(), secret) {
+        ("none", _) => return Signed::default(),
+        (_, None) => {
+            return Signed {
+                unsigned_reason: Some("webhook has no active secret to sign with".into()),
+                ..Default::default()
+            }
+        }
+        (_, Some(secret)) => secret,
+    };
+
+    let ts = now.timestamp().to_string();
+    let mut headers = Vec::new();
+    let mut query = None;
+    let mut add = |name: &str, value: String| headers.push((name.to_string(), value));
+    let result: Result<(), String> = match wh.verification_method.as_str() {
+        "github-hmac" => {
+            let mac = hmac256(secret.as_bytes(), &[body]);
+            add("x-hub-signature-256", format!("sha256={}", hex::encode(mac)));
+            Ok(())
+        }
+        "standard-webhooks" => {
+            let msg_id = format!("msg_test_{}", uuid::Uuid::new_v4().simple());
+            let encoded = secret.strip_prefix("whsec_").unwrap_or(secret);
+            let key = base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .unwrap_or_else(|_| encoded.as_bytes().to_vec());
+            let mac = hmac256(&key, &[msg_id.as_bytes(), b".", ts.as_bytes(), b".", body]);
+            add("webhook-id", msg_id);
+            add("webhook-timestamp", ts.clone());
+            let signature = base64::engine::general_purpose::STANDARD.encode(mac);
+            add("webhook-signature", format!("v1,{signature}"));
+            Ok(())
+        }
+        "custom-header" => match secret.split_once(':') {
+            Some((name, value)) => {
+                add(name, value.to_string());
+                Ok(())
+            }
+            None => Err("custom-header secret is not 'Header-Name:value'".into()),
+        },
+        "stripe" => {
+            let mac = hmac256(secret.as_bytes(), &[ts.as_bytes(), b".", body]);
+            add("stripe-signature", format!("t={ts},v1={}", hex::encode(mac)));
+            Ok(())
+        }
+        "slack" => {
+            let mac = hmac256(secret.as_bytes(), &[b"v0:", ts.as_bytes(), b":", body]);
+            add("x-slack-request-timestamp", ts.clone());
+            add("x-slack-signature", format!("v0={}", hex::encode(mac)));
+            Ok(())
+        }
+        "shopify" => {
+            let mac = hmac256(secret.as_bytes(), &[body]);
+            add("x-shopify-hmac-sha256", base64::engine::general_purpose::STANDARD.encode(mac));
+            Ok(())
+        }
+        "gitlab" => {
+            add("x-gitlab-token", secret.to_string());
+            Ok(())
+        }
+        "linear" => {
+            add("linear-signature", hex::encode(hmac256(secret.as_bytes(), &[body])));
+            Ok(())
+        }
+        "twilio" => {
+            // Form parameters are signed; a JSON body is covered by its hash
+            // in the query string instead.
+            let mut params: Vec<(String, String)> = Vec::new();
+            if content_type.starts_with("application/x-www-form-urlencoded") {
+                params = form_urlencoded::parse(body).into_owned().collect();
+            } else {
+                query = Some(format!("bodySHA256={}", hex::encode(Sha256::digest(body))));
+            }
+            let url = match &query {
+                Some(query) => format!("{}?{query}", wh.url),
+                None => wh.url.clone(),
+            };
+            params.sort();
+            let mut mac =
+                Hmac::<Sha1>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key");
+            mac.update(url.as_bytes());
+            for (name, value) in &params {
+                mac.update(name.as_bytes());
+                mac.update(value.as_bytes());
+            }
+            let signature = mac.finalize().into_bytes();
+            add("x-twilio-signature", base64::engine::general_purpose::STANDARD.encode(signature));
+            Ok(())
+        }
+        "bearer" => {
+            add("authorization", format!("Bearer {secret}"));
+            Ok(())
+        }
+        "basic" => {
+            let credentials = base64::engine::general_purpose::STANDARD.encode(secret);
+            add("authorization", format!("Basic {credentials}"));
+            Ok(())
+        }
+        "jwt" => {
+            let config = wh.jwt.clone().unwrap_or_default();
+            // RS256/ES256 tokens can be checked with the JWKS but not made.
+            if config.algorithms.is_empty() || config.algorithms.iter().any(|a| a == "HS256") {
+                add("authorization", format!("Bearer {}", hs256_token(secret, &config, now)));
+                Ok(())
+            } else {
+                Err("RS256/ES256 tokens need the sender's private key; \
+                     pass an authorization header"
+                    .into())
+            }
+        }
+        other => Err(format!("unknown verification method '{other}'")),
+    };
+    Signed {
+        headers,
+        query,
+        unsigned_reason: result.err(),
+    }
+}
+
+fn hmac256(key: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// A short-lived HS256 token that meets the webhook's claim constraints.
+fn hs256_token(secret: &str, config: &crate::jwt::JwtConfig, now: DateTime<Utc>) -> String {
+    let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let mut claims = serde_json::json!({
+        "sub": "webhook-receiver-test",
+        "iat": now.timestamp(),
+        "exp": now.timestamp() + 300,
+    });
+    if let Some(issuer) = &config.issuer {
+        claims["iss"] = Value::String(issuer.clone());
+    }
+    if let Some(audience) = config.audience.first() {
+        claims["aud"] = Value::String(audience.clone());
+    }
+    let input = format!(
+        "{}.{}",
+        engine.encode(br#"{"alg":"HS256","typ":"JWT"}"#),
+        engine.encode(claims.to_string())
+    );
+    let signature = hmac256(secret.as_bytes(), &[input.as_bytes()]);
+    format!("{input}.{}", engine.encode(signature))
+}
+
+/// What the listener answered.
+pub struct Sent {
+    pub status: u16,
+    pub body: String,
+}
+
+/// POST to the receiver's own listener at `listen_addr`, over TLS if it
+/// serves TLS.
+pub async fn send(
+    listen_addr: SocketAddr,
+    tls: Option<Arc<CertResolver>>,
+    path: &str,
+    headers: &[(String, String)],
+    body: Vec<u8>,
+    timeout: Duration,
+) -> Result<Sent, String> {
+    // A wildcard bind is reached over loopback.
+    let ip = match listen_addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    let addr = SocketAddr::new(ip, listen_addr.port());
+
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri(path)
+        .header(header::HOST, addr.to_string());
+    for (name, value) in headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    let request = request
+        .body(Full::new(Bytes::from(body)))
+        .map_err(|e| format!("invalid test request: {e}"))?;
+
+    let exchange = async {
+        let tcp = TcpStream::connect(addr)
+            .await
+            .map_err(|e| format!("failed to connect to {addr}: {e}"))?;
+        match tls {
+            Some(resolver) => {
+                let stream = tls::self_connector(resolver)?
+                    .connect(ip.into(), tcp)
+                    .await
+                    .map_err(|e| format!("TLS handshake with {addr} failed: {e}"))?;
+                exchange(stream, request).await
+            }
+            None => exchange(tcp, request).await,
+        }
+    };
+    tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| format!("no response from {addr} within {timeout:?}"))?
+}
+
+async fn exchange<S>(stream: S, request: Request<Full<Bytes>>) -> Result<Sent, String>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| format!("HTTP handshake failed: {e}"))?;
+    tokio::spawn(conn);
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| format!("request failed: {e}"))?;
+    let status = response.status().as_u16();
+    let body = Limited::new(response.into_body(), MAX_RESPONSE_BYTES)
+        .collect()
+        .await
+        .map(|b| String::from_utf8_lossy(&b.to_bytes()).into_owned())
+        .unwrap_or_else(|_| "[response body too large or incomplete]".into());
+    Ok(Sent { status, body })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secrets::WebhookSecret;
+
+    fn webhook(method: &str, secret: &str) -> Webhook {
+        serde_json::from_value(serde_json::json!({
+            "id": "wh_test",
+            "name": "test",
+            "event_type": "com.example.test",
+            "verification_method": method,
+            "secrets": [WebhookSecret::new(secret.into())],
+            "status": "active",
+            "url": "https://example.com/hooks/wh_test",
+            "trigger_count": 0,
+            "last_triggered": null,
+            "created_at": "2026-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn header<'a>(signed: &'a Signed, name: &str) -> &'a str {
+        &signed.headers.iter().find(|(n, _)| n == name).unwrap().1
+    }
+
+    // Each signature must pass the verifier it is meant for.
+    #[test]
+    fn signatures_verify() {
+        use crate::verification as v;
+        let body = br#"{"ok":true}"#;
+        let now = Utc::now();
+        let ts = now.timestamp();
+        let sign = |method, secret| sign(&webhook(method, secret), body, "application/json", now);
+
+        let s = sign("github-hmac", "gh");
+        assert_eq!(v::verify_github_hmac("gh", body, header(&s, "x-hub-signature-256")), Ok(()));
+
+        let s = sign("standard-webhooks", "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw");
+        assert_eq!(
+            v::verify_standard_webhooks(
+                "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw",
+                body,
+                header(&s, "webhook-id"),
+                header(&s, "webhook-timestamp"),
+                header(&s, "webhook-signature"),
+                300,
+                ts,
+            ),
+            Ok(())
+        );
+
+        let s = sign("stripe", "whsec_x");
+        assert_eq!(v::verify_stripe("whsec_x", body, header(&s, "stripe-signature"), 300, ts), Ok(()));
+
+        let s = sign("twilio", "auth");
+        let url = format!("https://example.com/hooks/wh_test?{}", s.query.as_deref().unwrap());
+        assert_eq!(v::verify_twilio("auth", &url, &[], body, header(&s, "x-twilio-signature")), Ok(()));
+
+        let s = sign("basic", "user:pass");
+        assert_eq!(v::verify_basic("user:pass", header(&s, "authorization")), Ok(()));
+
+        let s = sign("jwt", "hs-secret");
+        let token = v::credentials(header(&s, "authorization"), "Bearer").unwrap();
+        let token = crate::jwt::Token::decode(token).unwrap();
+        assert_eq!(token.verify_hmac("hs-secret"), Ok(()));
+
+        let mut wh = webhook("slack", "x");
+        wh.secrets.clear();
+        assert!(super::sign(&wh, body, "application/json", now).unsigned_reason.is_some());
+    }
+}
@@ -1,14 +1,30 @@
-use crate::types::{Delivery, Webhook};
+use crate::bridge::SyncReply;
+use crate::response::ResponseTemplate;
+use crate::secrets::{self, Cipher, WebhookSecret};
+use crate::types::{Delivery, ServerSettings, Webhook};
 use chrono::Utc;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::PathBuf;
+use std::sync::OnceLock;
 
 const MAX_DELIVERIES_PER_WEBHOOK: usize = 50;
+const MAX_UNMATCHED: usize = 50;
 
 pub struct WebhookStore {
     pub webhooks: HashMap<String, Webhook>,
     pub deliveries: HashMap<String, VecDeque<Delivery>>,
+    /// Requests for webhook ids that don't exist, kept apart so they can't
+    /// grow per-id state.
+    pub unmatched: VecDeque<Delivery>,
+    pub settings: ServerSettings,
     data_dir: PathBuf,
+    /// Encrypts secrets in `webhooks.json`; opened on first load or save.
+    cipher: OnceLock<Cipher>,
+    /// Delivery logs holding rejected or skipped requests not yet written
+    /// out, by file key.
+    unsaved_deliveries: HashSet<String>,
+    /// Failure counters changed since `webhooks.json` was last written.
+    unsaved_counters: bool,
 }
 
 impl WebhookStore {
@@ -16,17 +32,44 @@ impl WebhookStore {
         Self {
             webhooks: HashMap::new(),
             deliveries: HashMap::new(),
+            unmatched: VecDeque::new(),
+            settings: ServerSettings::default(),
             data_dir,
+            cipher: OnceLock::new(),
+            unsaved_deliveries: HashSet::new(),
+            unsaved_counters: false,
         }
     }
 
     pub fn load(&mut self) -> Result<(), String> {
+        let settings_path = self.data_dir.join("server.json");
+        if settings_path.exists() {
+            let content = std::fs::read_to_string(&settings_path)
+                .map_err(|e| format!("failed to read server.json: {e}"))?;
+            self.settings = serde_json::from_str(&content)
+                .map_err(|e| format!("failed to parse server.json: {e}"))?;
+        }
+
         let webhooks_path = self.data_dir.join("webhooks.json");
         if webhooks_path.exists() {
             let content = std::fs::read_to_string(&webhooks_path)
                 .map_err(|e| format!("failed to read webhooks.json: {e}"))?;
-            let items: Vec<Webhook> = serde_json::from_str(&content)
+            let mut items: Vec<Webhook> = serde_json::from_str(&content)
                 .map_err(|e| format!("failed to parse webhooks.json: {e}"))?;
+            let cipher = self.cipher()?;
+            for wh in &mut items {
+                for secret in &mut wh.secrets {
+                    secret.value = cipher.decrypt(&secret.value)?;
+                }
+                if let Some(token) = &mut wh.challenge_token {
+                    *token = cipher.decrypt(token)?;
+                }
+                if let Some(value) = wh.verification_secret.take().filter(|v| !v.is_empty()) {
+                    if wh.secrets.is_empty() {
+                        wh.secrets.push(WebhookSecret::new(value));
+                    }
+                }
+            }
             for wh in items {
                 self.webhooks.insert(wh.id.clone(), wh);
             }
@@ -35,7 +78,7 @@ impl WebhookStore {
         // Load deliveries for each webhook
         let deliveries_dir = self.data_dir.join("deliveries");
         if deliveries_dir.exists() {
-            for (id, _) in &self.webhooks {
+            for id in self.webhooks.keys() {
                 let path = deliveries_dir.join(format!("{id}.json"));
                 if path.exists() {
                     if let Ok(content) = std::fs::read_to_string(&path) {
@@ -46,26 +89,65 @@ impl WebhookStore {
                     }
                 }
             }
+            let path = deliveries_dir.join("_unmatched.json");
+            if let Ok(content) = std::fs::read_to_string(&path) {
+                if let Ok(items) = serde_json::from_str::<Vec<Delivery>>(&content) {
+                    self.unmatched = VecDeque::from(items);
+                }
+            }
         }
 
         Ok(())
     }
 
+    /// The at-rest cipher, which also keys secret fingerprints.
+    pub fn cipher(&self) -> Result<&Cipher, String> {
+        if let Some(cipher) = self.cipher.get() {
+            return Ok(cipher);
+        }
+        let cipher = Cipher::open(&self.data_dir)?;
+        Ok(self.cipher.get_or_init(|| cipher))
+    }
+
     pub fn save_webhooks(&self) -> Result<(), String> {
         let path = self.data_dir.join("webhooks.json");
-        let items: Vec<&Webhook> = self.webhooks.values().collect();
+        let cipher = self.cipher()?;
+        let mut items: Vec<Webhook> = Vec::with_capacity(self.webhooks.len());
+        for wh in self.webhooks.values() {
+            let mut wh = wh.clone();
+            for secret in &mut wh.secrets {
+                secret.value = cipher.encrypt(&secret.value)?;
+            }
+            if let Some(token) = &mut wh.challenge_token {
+                *token = cipher.encrypt(token)?;
+            }
+            items.push(wh);
+        }
         let content = serde_json::to_string_pretty(&items)
             .map_err(|e| format!("failed to serialize webhooks: {e}"))?;
         std::fs::write(&path, content).map_err(|e| format!("failed to write webhooks.json: {e}"))
     }
 
+    pub fn save_settings(&self) -> Result<(), String> {
+        std::fs::create_dir_all(&self.data_dir)
+            .map_err(|e| format!("failed to create data dir: {e}"))?;
+        let path = self.data_dir.join("server.json");
+        let content = serde_json::to_string_pretty(&self.settings)
+            .map_err(|e| format!("failed to serialize server settings: {e}"))?;
+        std::fs::write(&path, content).map_err(|e| format!("failed to write server.json: {e}"))
+    }
+
     pub fn save_deliveries(&self, webhook_id: &str) -> Result<(), String> {
         let dir = self.data_dir.join("deliveries");
         std::fs::create_dir_all(&dir)
             .map_err(|e| format!("failed to create deliveries dir: {e}"))?;
         let path = dir.join(format!("{webhook_id}.json"));
         let empty = VecDeque::new();
-        let deliveries = self.deliveries.get(webhook_id).unwrap_or(&empty);
+        let deliveries = if webhook_id == "_unmatched" {
+            &self.unmatched
+        } else {
+            self.deliveries.get(webhook_id).unwrap_or(&empty)
+        };
         let items: Vec<&Delivery> = deliveries.iter().collect();
         let content = serde_json::to_string_pretty(&items)
             .map_err(|e| format!("failed to serialize deliveries: {e}"))?;
@@ -90,24 +172,102 @@ impl WebhookStore {
         self.webhooks.get(&id).unwrap().clone()
     }
 
-    pub fn update(&mut self, id: &str, data: serde_json::Value) -> Option<Webhook> {
-        let wh = self.webhooks.get_mut(id)?;
+    /// Apply the fields present in `data`. Fields that can be invalid are
+    /// parsed before anything changes, so an error leaves the webhook as it
+    /// was.
+    pub fn update(
+        &mut self,
+        id: &str,
+        data: serde_json::Value,
+    ) -> Result<Option<Webhook>, String> {
+        // null clears the template.
+        let response = data
+            .get("response")
+            .map(|v| match v {
+                serde_json::Value::Null => Ok(None),
+                v => ResponseTemplate::parse(v).map(Some),
+            })
+            .transpose()?;
+        // null turns it off.
+        let sync_reply = data
+            .get("sync_reply")
+            .map(|v| match v {
+                serde_json::Value::Null => Ok(None),
+                v => SyncReply::parse(v).map(Some),
+            })
+            .transpose()?;
+        // null clears them.
+        let new_secrets = data
+            .get("secrets")
+            .map(|v| match v {
+                serde_json::Value::Null => Ok(Vec::new()),
+                v => secrets::parse_secrets(v),
+            })
+            .transpose()?;
+        let Some(wh) = self.webhooks.get_mut(id) else {
+            return Ok(None);
+        };
         if let Some(v) = data.get("name").and_then(|v| v.as_str()) {
             wh.name = v.to_string();
         }
         if let Some(v) = data.get("event_type").and_then(|v| v.as_str()) {
             wh.event_type = v.to_string();
         }
+        if let Some(v) = data.get("rules") {
+            // Validated by the caller; null clears them.
+            wh.rules = serde_json::from_value(v.clone()).unwrap_or_default();
+        }
+        if let Some(v) = data.get("dedupe_key") {
+            wh.dedupe_key = v.as_str().filter(|s| !s.is_empty()).map(|s| s.to_string());
+        }
+        if let Some(v) = data.get("dedupe_window_seconds") {
+            wh.dedupe_window_seconds = v.as_u64();
+        }
+        if let Some(v) = data.get("event_type_template") {
+            wh.event_type_template = v.as_str().filter(|s| !s.is_empty()).map(|s| s.to_string());
+        }
         if let Some(v) = data.get("verification_method").and_then(|v| v.as_str()) {
             wh.verification_method = v.to_string();
         }
-        if data.get("verification_secret").is_some() {
-            wh.verification_secret = data["verification_secret"].as_str().map(|s| s.to_string());
+        if let Some(v) = data.get("verification_secret") {
+            // A single secret replaces any being rotated.
+            wh.secrets = v
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .map(|s| WebhookSecret::new(s.to_string()))
+                .into_iter()
+                .collect();
+        }
+        if let Some(new_secrets) = new_secrets {
+            wh.secrets = new_secrets;
+        }
+        if let Some(v) = data.get("jwt") {
+            // Validated by the caller; null clears it.
+            wh.jwt = crate::jwt::JwtConfig::parse(v).ok();
+        }
+        if let Some(v) = data.get("timestamp_tolerance_seconds") {
+            wh.timestamp_tolerance_seconds = v.as_u64();
+        }
+        if let Some(v) = data.get("challenge_mode") {
+            wh.challenge_mode = v.as_str().filter(|s| !s.is_empty()).map(|s| s.to_string());
+        }
+        if let Some(v) = data.get("challenge_token") {
+            wh.challenge_token = v.as_str().filter(|s| !s.is_empty()).map(|s| s.to_string());
+        }
+        if let Some(sync_reply) = sync_reply {
+            wh.sync_reply = sync_reply;
+        }
+        if let Some(response) = response {
+            wh.response = response;
+        }
+        if let Some(v) = data.get("limits") {
+            // Validated by the caller; null clears them.
+            wh.limits = serde_json::from_value(v.clone()).unwrap_or_default();
         }
         if let Some(v) = data.get("status").and_then(|v| v.as_str()) {
             wh.status = v.to_string();
         }
-        Some(wh.clone())
+        Ok(Some(wh.clone()))
     }
 
     pub fn delete(&mut self, id: &str) -> bool {
@@ -129,11 +289,169 @@ impl WebhookStore {
         }
     }
 
+    /// Record a rejected request against its webhook, or in the unmatched
+    /// log if the webhook doesn't exist. Only memory is updated; the change
+    /// is written by the next `flush_unsaved`.
+    pub fn record_rejection(&mut self, delivery: Delivery) {
+        let id = delivery.webhook_id.clone();
+        let Some(wh) = self.webhooks.get_mut(&id) else {
+            if self.unmatched.len() >= MAX_UNMATCHED {
+                self.unmatched.pop_front();
+            }
+            self.unmatched.push_back(delivery);
+            self.unsaved_deliveries.insert("_unmatched".to_string());
+            return;
+        };
+        wh.failure_count += 1;
+        wh.last_failure = Some(Utc::now().to_rfc3339());
+        self.unsaved_counters = true;
+
+        let queue = self.deliveries.entry(id.clone()).or_default();
+        if queue.len() >= MAX_DELIVERIES_PER_WEBHOOK {
+            queue.pop_front();
+        }
+        queue.push_back(delivery);
+        self.unsaved_deliveries.insert(id);
+    }
+
+    /// Record a request that wasn't published, because one of its webhook's
+    /// rules dropped it, it was a duplicate or it was an endpoint handshake.
+    /// It counts as neither a trigger nor a failure. Written by the next
+    /// `flush_unsaved`.
+    pub fn record_skipped(&mut self, delivery: Delivery) {
+        let id = delivery.webhook_id.clone();
+        let queue = self.deliveries.entry(id.clone()).or_default();
+        if queue.len() >= MAX_DELIVERIES_PER_WEBHOOK {
+            queue.pop_front();
+        }
+        queue.push_back(delivery);
+        self.unsaved_deliveries.insert(id);
+    }
+
+    /// Whether `flush_unsaved` has anything to write.
+    pub fn has_unsaved(&self) -> bool {
+        self.unsaved_counters || !self.unsaved_deliveries.is_empty()
+    }
+
+    /// Write the delivery logs and counters changed by `record_rejection`
+    /// and `record_skipped` since the last flush.
+    pub fn flush_unsaved(&mut self) -> Result<(), String> {
+        for key in std::mem::take(&mut self.unsaved_deliveries) {
+            // Deleted webhooks have nothing left to write.
+            if key == "_unmatched" || self.webhooks.contains_key(&key) {
+                self.save_deliveries(&key)?;
+            }
+        }
+        if std::mem::take(&mut self.unsaved_counters) {
+            self.save_webhooks()?;
+        }
+        Ok(())
+    }
+
+    pub fn find_delivery(&self, delivery_id: &str) -> Option<&Delivery> {
+        self.deliveries
+            .values()
+            .flat_map(|q| q.iter())
+            .find(|d| d.id == delivery_id)
+    }
+
     pub fn get_deliveries(&self, webhook_id: &str) -> Vec<&Delivery> {
         self.deliveries
             .get(webhook_id)
             .map(|q| q.iter().rev().collect())
             .unwrap_or_default()
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rejected(webhook_id: &str) -> Delivery {
+        serde_json::from_value(json!({
+            "id": uuid::Uuid::new_v4().to_string(),
+            "webhook_id": webhook_id,
+            "received_at": "2026-03-01T00:00:00Z",
+            "status": "rejected",
+            "reason": "rate_limited",
+            "headers": {},
+            "body": null,
+        }))
+        .unwrap()
+    }
+
+    fn webhook() -> Webhook {
+        serde_json::from_value(json!({
+            "id": "wh_test",
+            "name": "Test",
+            "event_type": "test.event",
+            "verification_method": "none",
+            "status": "active",
+            "url": "http://127.0.0.1/hooks/wh_test",
+            "trigger_count": 0,
+            "last_triggered": null,
+            "created_at": "2026-03-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn invalid_update_changes_nothing() {
+        let mut store = WebhookStore::new(std::env::temp_dir());
+        store.insert(webhook());
+
+        let bad = json!({ "name": "Renamed", "response": { "status": 500 } });
+        assert!(store.update("wh_test", bad).is_err());
+        let wh = store.get("wh_test").unwrap();
+        assert_eq!(wh.name, "Test");
+        assert!(wh.response.is_none());
+
+        let good = json!({ "response": { "status": 202, "body": "ok" } });
+        let wh = store.update("wh_test", good).unwrap().unwrap();
+        assert_eq!(wh.response.unwrap().status, Some(202));
+        let wh = store.update("wh_test", json!({ "response": null })).unwrap().unwrap();
+        assert!(wh.response.is_none());
+        assert!(store.update("wh_missing", json!({})).unwrap().is_none());
 
+        for bad in [json!({ "timeout_ms": 0 }), json!({ "timeout_ms": 60_001 }), json!("on")] {
+            assert!(store.update("wh_test", json!({ "sync_reply": bad })).is_err());
+            assert!(store.get("wh_test").unwrap().sync_reply.is_none());
+        }
+        store.update("wh_test", json!({ "verification_secret": "kept" })).unwrap();
+        let bad = json!({ "secrets": [{ "value": "" }] });
+        assert!(store.update("wh_test", bad).is_err());
+        assert_eq!(store.get("wh_test").unwrap().secrets[0].value, "kept");
+
+        let good = json!({ "sync_reply": { "timeout_ms": 1_000 } });
+        let wh = store.update("wh_test", good).unwrap().unwrap();
+        assert_eq!(wh.sync_reply.unwrap().timeout_ms, 1_000);
+    }
+
+    #[test]
+    fn rejections_are_written_on_flush() {
+        let dir = std::env::temp_dir().join(format!("store-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut store = WebhookStore::new(dir.clone());
+        store.insert(webhook());
+
+        for _ in 0..3 {
+            store.record_rejection(rejected("wh_test"));
+        }
+        store.record_rejection(rejected("wh_missing"));
+        assert_eq!(store.get("wh_test").unwrap().failure_count, 3);
+        assert!(store.has_unsaved());
+        assert!(!dir.join("webhooks.json").exists());
+        assert!(!dir.join("deliveries").exists());
+
+        store.flush_unsaved().unwrap();
+        assert!(!store.has_unsaved());
+        let mut reloaded = WebhookStore::new(dir.clone());
+        reloaded.load().unwrap();
+        assert_eq!(reloaded.get("wh_test").unwrap().failure_count, 3);
+        assert_eq!(reloaded.get_deliveries("wh_test").len(), 3);
+        assert_eq!(reloaded.unmatched.len(), 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
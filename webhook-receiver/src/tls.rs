@@ -0,0 +1,373 @@
+use axum::serve::Listener;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::WebPkiSupportedAlgorithms;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// How often the certificate files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Slow or stalled handshakes are dropped after this long so they can't pile
+/// up.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves the current certificate and swaps in a new one when the files on
+/// disk change, without restarting the listener.
+#[derive(Debug)]
+pub struct CertResolver {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Loaded>,
+}
+
+#[derive(Debug)]
+struct Loaded {
+    key: Arc<CertifiedKey>,
+    fingerprint: String,
+    modified: (Option<SystemTime>, Option<SystemTime>),
+}
+
+impl CertResolver {
+    pub fn new(cert_path: PathBuf, key_path: PathBuf) -> Result<Arc<Self>, String> {
+        let loaded = load(&cert_path, &key_path)?;
+        Ok(Arc::new(Self {
+            cert_path,
+            key_path,
+            current: RwLock::new(loaded),
+        }))
+    }
+
+    /// SHA-256 fingerprint of the leaf certificate, as colon-separated hex.
+    pub fn fingerprint(&self) -> String {
+        self.current.read().unwrap().fingerprint.clone()
+    }
+
+    pub fn cert_path(&self) -> &Path {
+        &self.cert_path
+    }
+
+    /// Poll the certificate files and reload them when either changes.
+    pub async fn watch(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            ticker.tick().await;
+            self.reload();
+        }
+    }
+
+    /// Reload the pair if either file changed since the last attempt. A pair
+    /// that fails to load, including a certificate and key that don't belong
+    /// together (as while a renewal has written one but not yet the other),
+    /// is logged and the previous one kept.
+    fn reload(&self) {
+        let modified = modified_times(&self.cert_path, &self.key_path);
+        if modified == self.current.read().unwrap().modified {
+            return;
+        }
+        match load(&self.cert_path, &self.key_path) {
+            Ok(loaded) => {
+                eprintln!(
+                    "webhook-receiver: reloaded TLS certificate {} ({})",
+                    self.cert_path.display(),
+                    loaded.fingerprint
+                );
+                *self.current.write().unwrap() = loaded;
+            }
+            Err(e) => {
+                eprintln!("webhook-receiver: keeping previous TLS certificate: {e}");
+                // Don't retry the same broken files every tick.
+                self.current.write().unwrap().modified = modified;
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().key.clone())
+    }
+}
+
+fn modified_times(cert: &Path, key: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let mtime = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    (mtime(cert), mtime(key))
+}
+
+fn load(cert_path: &Path, key_path: &Path) -> Result<Loaded, String> {
+    let modified = modified_times(cert_path, key_path);
+
+    let cert_pem = std::fs::read(cert_path)
+        .map_err(|e| format!("failed to read {}: {e}", cert_path.display()))?;
+    let certs = rustls_pemfile::certs(&mut cert_pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("failed to parse {}: {e}", cert_path.display()))?;
+    let leaf = certs
+        .first()
+        .ok_or_else(|| format!("no certificate found in {}", cert_path.display()))?;
+    let fingerprint = fingerprint(leaf);
+
+    let key_pem = std::fs::read(key_path)
+        .map_err(|e| format!("failed to read {}: {e}", key_path.display()))?;
+    let key = rustls_pemfile::private_key(&mut key_pem.as_slice())
+        .map_err(|e| format!("failed to parse {}: {e}", key_path.display()))?
+        .ok_or_else(|| format!("no private key found in {}", key_path.display()))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+        .map_err(|e| format!("unsupported private key in {}: {e}", key_path.display()))?;
+
+    let key = CertifiedKey::new(certs, signing_key);
+    key.keys_match().map_err(|e| {
+        format!(
+            "{} and {} don't match: {e}",
+            cert_path.display(),
+            key_path.display()
+        )
+    })?;
+
+    Ok(Loaded {
+        key: Arc::new(key),
+        fingerprint,
+        modified,
+    })
+}
+
+fn fingerprint(cert: &CertificateDer<'_>) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Return the self-signed certificate pair under `data_dir/tls`, generating
+/// it for `hosts` if it doesn't exist yet. Delete the files to regenerate
+/// (e.g. after changing the public base URL).
+pub fn ensure_self_signed(data_dir: &Path, hosts: Vec<String>) -> Result<(PathBuf, PathBuf), String> {
+    let dir = data_dir.join("tls");
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    if cert_path.exists() && key_path.exists() {
+        return Ok((cert_path, key_path));
+    }
+
+    std::fs::create_dir_all(&dir).map_err(|e| format!("failed to create tls dir: {e}"))?;
+    let generated = rcgen::generate_simple_self_signed(hosts.clone())
+        .map_err(|e| format!("failed to generate self-signed certificate: {e}"))?;
+    write_private(&key_path, generated.key_pair.serialize_pem().as_bytes())?;
+    std::fs::write(&cert_path, generated.cert.pem())
+        .map_err(|e| format!("failed to write {}: {e}", cert_path.display()))?;
+    eprintln!(
+        "webhook-receiver: generated self-signed TLS certificate for {}",
+        hosts.join(", ")
+    );
+    Ok((cert_path, key_path))
+}
+
+fn write_private(path: &Path, content: &[u8]) -> Result<(), String> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut f| f.write_all(content))
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+pub fn acceptor(resolver: Arc<CertResolver>) -> Result<TlsAcceptor, String> {
+    let mut config =
+        rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("failed to configure TLS: {e}"))?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// A connector for requests the receiver sends to its own listener. It
+/// trusts exactly the certificate the listener is serving, whoever issued it.
+pub fn self_connector(resolver: Arc<CertResolver>) -> Result<TlsConnector, String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = PinnedCert {
+        resolver,
+        algorithms: provider.signature_verification_algorithms,
+    };
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("failed to configure TLS: {e}"))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+#[derive(Debug)]
+struct PinnedCert {
+    resolver: Arc<CertResolver>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if fingerprint(end_entity) == self.resolver.fingerprint() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("not the listener's certificate".into()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// A listener that yields connections once their TLS handshake completes.
+/// Handshakes run on their own tasks so one slow client can't hold up
+/// accepting others.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    ready: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub fn new(tcp: TcpListener, acceptor: TlsAcceptor) -> Result<Self, String> {
+        let local_addr = tcp.local_addr().map_err(|e| e.to_string())?;
+        let (tx, ready) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                let (stream, remote) = match tcp.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        eprintln!("webhook-receiver: accept failed: {e}");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(tls)) => {
+                            let _ = tx.send((tls, remote)).await;
+                        }
+                        Ok(Err(e)) => {
+                            eprintln!("webhook-receiver: TLS handshake with {remote} failed: {e}")
+                        }
+                        Err(_) => eprintln!("webhook-receiver: TLS handshake with {remote} timed out"),
+                    }
+                });
+            }
+        });
+        Ok(Self { local_addr, ready })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.ready.recv().await {
+            Some(conn) => conn,
+            // The accept loop never exits, so this can't happen.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("tls-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn fingerprint_is_colon_separated_sha256() {
+        let fp = fingerprint(&CertificateDer::from(b"abc".to_vec()));
+        assert_eq!(fp.len(), 32 * 3 - 1);
+        assert!(fp.starts_with("BA:78:16:BF:8F:01:CF:EA:"));
+        assert!(fp.ends_with(":F2:00:15:AD"));
+    }
+
+    #[test]
+    fn self_signed_pair_is_generated_once() {
+        let dir = temp_dir();
+        let (cert, key) = ensure_self_signed(&dir, vec!["localhost".into()]).unwrap();
+        let first = (std::fs::read(&cert).unwrap(), std::fs::read(&key).unwrap());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&key).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let again = ensure_self_signed(&dir, vec!["example.com".into()]).unwrap();
+        assert_eq!(again, (cert.clone(), key.clone()));
+        assert_eq!((std::fs::read(&cert).unwrap(), std::fs::read(&key).unwrap()), first);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn mismatched_pair_keeps_previous_certificate() {
+        let (dir, other) = (temp_dir(), temp_dir());
+        let (cert, key) = ensure_self_signed(&dir, vec!["localhost".into()]).unwrap();
+        let (new_cert, new_key) = ensure_self_signed(&other, vec!["localhost".into()]).unwrap();
+        let resolver = CertResolver::new(cert.clone(), key.clone()).unwrap();
+        let original = resolver.fingerprint();
+
+        // A renewal that has written the certificate but not yet the key.
+        std::fs::copy(&new_cert, &cert).unwrap();
+        assert!(load(&cert, &key).unwrap_err().contains("don't match"));
+        resolver.reload();
+        assert_eq!(resolver.fingerprint(), original);
+
+        std::fs::copy(&new_key, &key).unwrap();
+        resolver.reload();
+        assert_ne!(resolver.fingerprint(), original);
+        assert_eq!(resolver.fingerprint(), load(&new_cert, &new_key).unwrap().fingerprint);
+        let _ = std::fs::remove_dir_all(dir);
+        let _ = std::fs::remove_dir_all(other);
+    }
+}
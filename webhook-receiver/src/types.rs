@@ -1,3 +1,9 @@
+use crate::bridge::SyncReply;
+use crate::jwt::JwtConfig;
+use crate::limits::Limits;
+use crate::response::ResponseTemplate;
+use crate::rules::Rule;
+use crate::secrets::WebhookSecret;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -6,12 +12,61 @@ pub struct Webhook {
     pub id: String,
     pub name: String,
     pub event_type: String,
+    /// Template for deriving the event type per delivery, e.g.
+    /// `com.github.{header:x-github-event}.{body:action}`. Falls back to
+    /// `event_type` when it doesn't render.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_type_template: Option<String>,
+    /// Filters and transformations applied before publishing, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+    /// Where to find a delivery's idempotency key: `header:<name>`,
+    /// `body:<path>` or `body_hash`. Unset disables deduplication.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedupe_key: Option<String>,
+    /// How long a key is remembered. Defaults to a day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedupe_window_seconds: Option<u64>,
     pub verification_method: String,
+    /// Secrets a request may be signed with; more than one while rotating.
+    #[serde(default)]
+    pub secrets: Vec<WebhookSecret>,
+    /// Single secret from before rotation support; read only to migrate it
+    /// into `secrets` on load.
+    #[serde(default, skip_serializing)]
     pub verification_secret: Option<String>,
+    /// Token constraints for the `jwt` method.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwt: Option<JwtConfig>,
+    /// Maximum clock skew accepted for timestamped signatures, in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_tolerance_seconds: Option<u64>,
+    /// Endpoint handshake to answer: `slack`, `meta`, `dropbox` or
+    /// `microsoft-graph`. Handshakes are never published.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge_mode: Option<String>,
+    /// Verify token expected in Meta's `hub.verify_token`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub challenge_token: Option<String>,
+    /// Hold requests open and answer with the host's reply to the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_reply: Option<SyncReply>,
+    /// What to answer accepted requests with. Unset sends an empty 200.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ResponseTemplate>,
+    /// Body size, rate and source address limits for this webhook, applied
+    /// on top of the server-wide ones.
+    #[serde(default, skip_serializing_if = "Limits::is_empty")]
+    pub limits: Limits,
     pub status: String,
     pub url: String,
     pub trigger_count: u64,
     pub last_triggered: Option<String>,
+    /// Requests rejected for this webhook (bad signature, paused, ...).
+    #[serde(default)]
+    pub failure_count: u64,
+    #[serde(default)]
+    pub last_failure: Option<String>,
     pub created_at: String,
 }
 
@@ -20,15 +75,98 @@ pub struct Delivery {
     pub id: String,
     pub webhook_id: String,
     pub received_at: DateTime<Utc>,
-    pub status: String, // "success" | "verification_failed"
+    pub status: String, // "success" | "filtered" | "duplicate" | "handshake" | "verification_failed" | "rejected" | "not_found" | "publish_failed"
     pub headers: serde_json::Value,
     pub body: serde_json::Value,
+    /// Query string parameters, if the request had any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<serde_json::Value>,
+    /// Per-webhook ordering sequence number assigned by the outbox.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+    /// Id of the original delivery when this one is a replay.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replayed_from: Option<String>,
+    /// Why the request was rejected, e.g. "bad_signature" or "paused".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_addr: Option<String>,
+    /// Id of the webhook secret the request was verified with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_id: Option<String>,
+    /// Claims of the verified JWT, for the `jwt` method.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claims: Option<serde_json::Value>,
 }
 
 /// A webhook delivery queued for event.publish, not yet sent to host.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PendingEvent {
     pub event_type: String,
     pub subject: String,
     pub data: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replayed_from: Option<String>,
+    /// Set when an HTTP request is waiting for a reply to this event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+/// Listener settings, persisted in `server.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerSettings {
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String,
+    /// Port to listen on. Unset reuses `last_port`, so URLs survive restarts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Externally reachable base URL (tunnel or reverse proxy) used for
+    /// webhook URLs instead of the listen address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_base_url: Option<String>,
+    /// The port actually bound last time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_port: Option<u16>,
+    /// Serve HTTPS. Without a cert/key pair, a self-signed certificate is
+    /// generated in the data dir.
+    #[serde(default)]
+    pub tls: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_cert_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_key_path: Option<String>,
+    /// Limits applied to every request, whichever webhook it targets.
+    #[serde(default, skip_serializing_if = "Limits::is_empty")]
+    pub limits: Limits,
+    /// Proxies whose `X-Forwarded-For` header is believed, as CIDRs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<String>,
+    /// Header names whose values are masked in stored deliveries. A leading
+    /// or trailing `*` is a wildcard. Unset means the built-in list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redacted_headers: Option<Vec<String>>,
+}
+
+fn default_bind_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            bind_address: default_bind_address(),
+            port: None,
+            public_base_url: None,
+            last_port: None,
+            tls: false,
+            tls_cert_path: None,
+            tls_key_path: None,
+            limits: Limits::default(),
+            trusted_proxies: Vec::new(),
+            redacted_headers: None,
+        }
+    }
 }
@@ -1,32 +1,91 @@
 use base64::Engine as _;
 use hmac::{Hmac, Mac};
-use sha2::Sha256;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
 
 type HmacSha256 = Hmac<Sha256>;
+type HmacSha1 = Hmac<Sha1>;
+
+/// Default timestamp tolerance for Standard Webhooks, Stripe and Slack, per
+/// their recommendations.
+pub const DEFAULT_TOLERANCE_SECS: u64 = 300;
+
+/// Linear recommends rejecting payloads whose `webhookTimestamp` is more than
+/// a minute off.
+pub const LINEAR_TOLERANCE_SECS: u64 = 60;
+
+/// Why a request failed verification. `as_str` is the reason code stored on
+/// the rejected delivery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    MissingHeader,
+    MissingSecret,
+    MalformedSecret,
+    BadSignature,
+    BadTimestamp,
+    StaleTimestamp,
+    UnknownMethod,
+    MalformedToken,
+    ExpiredToken,
+    BadClaims,
+    UnknownKey,
+}
+
+impl VerifyError {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::MissingHeader => "missing_header",
+            Self::MissingSecret => "missing_secret",
+            Self::MalformedSecret => "malformed_secret",
+            Self::BadSignature => "bad_signature",
+            Self::BadTimestamp => "bad_timestamp",
+            Self::StaleTimestamp => "stale_timestamp",
+            Self::UnknownMethod => "unknown_method",
+            Self::MalformedToken => "malformed_token",
+            Self::ExpiredToken => "expired_token",
+            Self::BadClaims => "bad_claims",
+            Self::UnknownKey => "unknown_key",
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 /// Verify a GitHub-style HMAC signature.
 /// Expects header value like "sha256=<hex>".
-pub fn verify_github_hmac(secret: &str, body: &[u8], signature_header: &str) -> bool {
-    let expected_hex = match signature_header.strip_prefix("sha256=") {
-        Some(h) => h,
-        None => return false,
-    };
-
-    let mut mac = match HmacSha256::new_from_slice(secret.as_bytes()) {
-        Ok(m) => m,
-        Err(_) => return false,
-    };
-    mac.update(body);
-    let result = mac.finalize().into_bytes();
-    let computed_hex = hex::encode(result);
+pub fn verify_github_hmac(
+    secret: &str,
+    body: &[u8],
+    signature_header: &str,
+) -> Result<(), VerifyError> {
+    let expected = signature_header
+        .strip_prefix("sha256=")
+        .and_then(|h| hex::decode(h).ok())
+        .ok_or(VerifyError::BadSignature)?;
 
-    // Constant-time comparison via hex string comparison is fine here —
-    // timing attacks on HMAC verification over localhost are not a concern.
-    computed_hex == expected_hex
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).map_err(|_| VerifyError::MalformedSecret)?;
+    mac.update(body);
+    mac.verify_slice(&expected)
+        .map_err(|_| VerifyError::BadSignature)
 }
 
 /// Verify a Standard Webhooks signature.
-/// Header format: "v1,<base64>" (may be comma-separated list of sigs).
+///
+/// - The secret is `whsec_<base64>`; the prefix is optional and a secret
+///   that isn't valid base64 is used as raw bytes.
+/// - `webhook-timestamp` must be within `tolerance_secs` of `now` (unix
+///   seconds) in either direction.
+/// - The header may carry several space-separated `v1,<base64>` signatures
+///   (e.g. during the sender's key rotation); any match passes. Other
+///   versions are ignored.
+///
 /// Signed payload: "<msg-id>.<timestamp>.<body>"
 pub fn verify_standard_webhooks(
     secret: &str,
@@ -34,31 +93,488 @@ pub fn verify_standard_webhooks(
     msg_id: &str,
     timestamp: &str,
     signature_header: &str,
-) -> bool {
-    // The secret may be base64-encoded (standard-webhooks uses base64 secrets)
-    let secret_bytes = match base64::engine::general_purpose::STANDARD.decode(secret) {
-        Ok(b) => b,
-        Err(_) => secret.as_bytes().to_vec(),
-    };
-
-    let signed_payload = format!("{}.{}.{}", msg_id, timestamp, String::from_utf8_lossy(body));
-
-    let mut mac = match HmacSha256::new_from_slice(&secret_bytes) {
-        Ok(m) => m,
-        Err(_) => return false,
-    };
-    mac.update(signed_payload.as_bytes());
-    let result = mac.finalize().into_bytes();
-    let computed_b64 = base64::engine::general_purpose::STANDARD.encode(result);
-    let expected_sig = format!("v1,{}", computed_b64);
-
-    // Header may contain multiple sigs separated by spaces
-    signature_header
+    tolerance_secs: u64,
+    now: i64,
+) -> Result<(), VerifyError> {
+    check_timestamp(timestamp, tolerance_secs, now)?;
+
+    let encoded = secret.strip_prefix("whsec_").unwrap_or(secret);
+    let secret_bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .unwrap_or_else(|_| encoded.as_bytes().to_vec());
+
+    let mut mac =
+        HmacSha256::new_from_slice(&secret_bytes).map_err(|_| VerifyError::MalformedSecret)?;
+    mac.update(msg_id.as_bytes());
+    mac.update(b".");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    let matched = signature_header
         .split_whitespace()
-        .any(|sig| sig == expected_sig)
+        .filter_map(|sig| sig.strip_prefix("v1,"))
+        .filter_map(|b64| base64::engine::general_purpose::STANDARD.decode(b64).ok())
+        .any(|sig| mac.clone().verify_slice(&sig).is_ok());
+
+    if matched {
+        Ok(())
+    } else {
+        Err(VerifyError::BadSignature)
+    }
+}
+
+/// Verify a Stripe signature.
+///
+/// The `Stripe-Signature` header looks like `t=<unix>,v1=<hex>,v0=<hex>`.
+/// The signing secret (`whsec_...`) is used verbatim as the HMAC key; only
+/// `v1` signatures count, and there may be several while a secret is rolled.
+///
+/// Signed payload: "<t>.<body>"
+pub fn verify_stripe(
+    secret: &str,
+    body: &[u8],
+    signature_header: &str,
+    tolerance_secs: u64,
+    now: i64,
+) -> Result<(), VerifyError> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in signature_header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", t)) => timestamp = Some(t),
+            Some(("v1", sig)) => signatures.extend(hex::decode(sig).ok()),
+            _ => {}
+        }
+    }
+    let timestamp = timestamp.ok_or(VerifyError::BadTimestamp)?;
+    check_timestamp(timestamp, tolerance_secs, now)?;
+
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).map_err(|_| VerifyError::MalformedSecret)?;
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    if signatures.iter().any(|sig| mac.clone().verify_slice(sig).is_ok()) {
+        Ok(())
+    } else {
+        Err(VerifyError::BadSignature)
+    }
+}
+
+/// Verify a Slack request signature.
+/// Expects `X-Slack-Signature` like "v0=<hex>" and the Unix timestamp from
+/// `X-Slack-Request-Timestamp`.
+///
+/// Signed payload: "v0:<timestamp>:<body>"
+pub fn verify_slack(
+    secret: &str,
+    body: &[u8],
+    timestamp: &str,
+    signature_header: &str,
+    tolerance_secs: u64,
+    now: i64,
+) -> Result<(), VerifyError> {
+    check_timestamp(timestamp, tolerance_secs, now)?;
+    let expected = signature_header
+        .strip_prefix("v0=")
+        .and_then(|h| hex::decode(h).ok())
+        .ok_or(VerifyError::BadSignature)?;
+
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).map_err(|_| VerifyError::MalformedSecret)?;
+    mac.update(b"v0:");
+    mac.update(timestamp.trim().as_bytes());
+    mac.update(b":");
+    mac.update(body);
+    mac.verify_slice(&expected)
+        .map_err(|_| VerifyError::BadSignature)
+}
+
+/// Verify a Shopify signature.
+/// Expects `X-Shopify-Hmac-Sha256` as the base64 HMAC-SHA256 of the body.
+pub fn verify_shopify(
+    secret: &str,
+    body: &[u8],
+    signature_header: &str,
+) -> Result<(), VerifyError> {
+    let expected = base64::engine::general_purpose::STANDARD
+        .decode(signature_header.trim())
+        .map_err(|_| VerifyError::BadSignature)?;
+
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).map_err(|_| VerifyError::MalformedSecret)?;
+    mac.update(body);
+    mac.verify_slice(&expected)
+        .map_err(|_| VerifyError::BadSignature)
+}
+
+/// Verify a Linear signature.
+///
+/// `Linear-Signature` is the hex HMAC-SHA256 of the body. The body's
+/// `webhookTimestamp` (Unix milliseconds) must be within `tolerance_secs` of
+/// `now`, since the signature itself carries no time.
+pub fn verify_linear(
+    secret: &str,
+    body: &[u8],
+    signature_header: &str,
+    tolerance_secs: u64,
+    now: i64,
+) -> Result<(), VerifyError> {
+    let expected = hex::decode(signature_header.trim()).map_err(|_| VerifyError::BadSignature)?;
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).map_err(|_| VerifyError::MalformedSecret)?;
+    mac.update(body);
+    mac.verify_slice(&expected)
+        .map_err(|_| VerifyError::BadSignature)?;
+
+    let sent_ms = serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v.get("webhookTimestamp").and_then(|t| t.as_i64()))
+        .ok_or(VerifyError::BadTimestamp)?;
+    if now.abs_diff(sent_ms / 1000) > tolerance_secs {
+        return Err(VerifyError::StaleTimestamp);
+    }
+    Ok(())
+}
+
+/// Verify a Twilio request signature.
+///
+/// `X-Twilio-Signature` is the base64 HMAC-SHA1, keyed by the auth token, of
+/// the full URL Twilio requested followed by every POST parameter as
+/// `<name><value>`, sorted by name. For JSON bodies there are no parameters;
+/// instead the URL carries a `bodySHA256` query parameter that must match
+/// the body's hex SHA-256.
+pub fn verify_twilio(
+    auth_token: &str,
+    url: &str,
+    params: &[(String, String)],
+    body: &[u8],
+    signature_header: &str,
+) -> Result<(), VerifyError> {
+    let expected = base64::engine::general_purpose::STANDARD
+        .decode(signature_header.trim())
+        .map_err(|_| VerifyError::BadSignature)?;
+
+    let mut sorted: Vec<&(String, String)> = params.iter().collect();
+    sorted.sort();
+    let mut mac =
+        HmacSha1::new_from_slice(auth_token.as_bytes()).map_err(|_| VerifyError::MalformedSecret)?;
+    mac.update(url.as_bytes());
+    for (name, value) in sorted {
+        mac.update(name.as_bytes());
+        mac.update(value.as_bytes());
+    }
+    mac.verify_slice(&expected)
+        .map_err(|_| VerifyError::BadSignature)?;
+
+    let body_hash = url
+        .split_once('?')
+        .into_iter()
+        .flat_map(|(_, query)| form_urlencoded::parse(query.as_bytes()))
+        .find(|(name, _)| name == "bodySHA256")
+        .map(|(_, hash)| hash.into_owned());
+    if let Some(hash) = body_hash {
+        let actual = hex::encode(Sha256::digest(body));
+        if !constant_time_eq(actual.as_bytes(), hash.to_ascii_lowercase().as_bytes()) {
+            return Err(VerifyError::BadSignature);
+        }
+    }
+    Ok(())
 }
 
 /// Verify a custom header value matches the secret exactly.
-pub fn verify_custom_header(secret: &str, header_value: &str) -> bool {
-    header_value == secret
+pub fn verify_custom_header(secret: &str, header_value: &str) -> Result<(), VerifyError> {
+    if constant_time_eq(secret.as_bytes(), header_value.as_bytes()) {
+        Ok(())
+    } else {
+        Err(VerifyError::BadSignature)
+    }
+}
+
+/// Extract the credentials of an `Authorization` header with the given
+/// scheme, compared case-insensitively.
+pub fn credentials<'a>(authorization: &'a str, scheme: &str) -> Option<&'a str> {
+    let (given, credentials) = authorization.trim().split_once(' ')?;
+    given
+        .eq_ignore_ascii_case(scheme)
+        .then(|| credentials.trim())
+        .filter(|c| !c.is_empty())
+}
+
+/// Verify `Authorization: Bearer <token>` against a static token.
+pub fn verify_bearer(secret: &str, authorization: &str) -> Result<(), VerifyError> {
+    let token = credentials(authorization, "Bearer").ok_or(VerifyError::BadSignature)?;
+    if constant_time_eq(secret.as_bytes(), token.as_bytes()) {
+        Ok(())
+    } else {
+        Err(VerifyError::BadSignature)
+    }
+}
+
+/// Verify `Authorization: Basic <base64>` against a `user:password` secret.
+pub fn verify_basic(secret: &str, authorization: &str) -> Result<(), VerifyError> {
+    if !secret.contains(':') {
+        return Err(VerifyError::MalformedSecret);
+    }
+    let given = credentials(authorization, "Basic")
+        .and_then(|c| base64::engine::general_purpose::STANDARD.decode(c).ok())
+        .ok_or(VerifyError::BadSignature)?;
+    if constant_time_eq(secret.as_bytes(), &given) {
+        Ok(())
+    } else {
+        Err(VerifyError::BadSignature)
+    }
+}
+
+/// Parse a Unix-seconds timestamp and check it is within `tolerance_secs` of
+/// `now` in either direction.
+fn check_timestamp(timestamp: &str, tolerance_secs: u64, now: i64) -> Result<(), VerifyError> {
+    let ts: i64 = timestamp
+        .trim()
+        .parse()
+        .map_err(|_| VerifyError::BadTimestamp)?;
+    if now.abs_diff(ts) > tolerance_secs {
+        return Err(VerifyError::StaleTimestamp);
+    }
+    Ok(())
+}
+
+/// Compare two byte strings without short-circuiting on the first mismatch.
+/// Length is not secret, so a length mismatch may return early.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Bounded memory of recently accepted message ids, used to reject replays
+/// of a captured request within its timestamp tolerance. Entries older than
+/// their TTL are useless (the timestamp check rejects them anyway), so they
+/// are evicted first; past capacity the oldest entry goes.
+pub struct SeenMessages {
+    capacity: usize,
+    order: VecDeque<(String, i64)>,
+    keys: HashSet<String>,
+}
+
+impl SeenMessages {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: VecDeque::new(),
+            keys: HashSet::new(),
+        }
+    }
+
+    /// Remember `key` until `expires_at`. Returns false if it was already seen.
+    pub fn insert(&mut self, key: String, now: i64, expires_at: i64) -> bool {
+        while let Some((old, exp)) = self.order.front() {
+            if *exp > now && self.order.len() < self.capacity {
+                break;
+            }
+            self.keys.remove(old);
+            self.order.pop_front();
+        }
+        if !self.keys.insert(key.clone()) {
+            return false;
+        }
+        self.order.push_back((key, expires_at));
+        true
+    }
+
+    /// Forget `key`, so the message it names is accepted again.
+    pub fn remove(&mut self, key: &str) {
+        if self.keys.remove(key) {
+            self.order.retain(|(k, _)| k != key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seen_messages_expire_and_can_be_forgotten() {
+        let mut seen = SeenMessages::new(2);
+        assert!(seen.insert("a".into(), 0, 10));
+        assert!(!seen.insert("a".into(), 5, 15));
+        seen.remove("a");
+        assert!(seen.insert("a".into(), 5, 15));
+        // Expired at 10 or pushed out by capacity.
+        assert!(seen.insert("b".into(), 12, 20));
+        assert!(seen.insert("c".into(), 12, 20));
+        assert!(seen.insert("a".into(), 16, 30));
+    }
+
+    // From GitHub's "Validating webhook deliveries" guide.
+    #[test]
+    fn github_docs_vector() {
+        let sig = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert_eq!(
+            verify_github_hmac("It's a Secret to Everybody", b"Hello, World!", sig),
+            Ok(())
+        );
+        assert_eq!(
+            verify_github_hmac("wrong", b"Hello, World!", sig),
+            Err(VerifyError::BadSignature)
+        );
+    }
+
+    // From the Standard Webhooks specification.
+    #[test]
+    fn standard_webhooks_spec_vector() {
+        let secret = "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw";
+        let body = br#"{"test": 2432232314}"#;
+        let sig = "v1,g0hM9SsE+OTPJTGt/tmIKtSyZlE3uFJELVlNIOLJ1OE=";
+        let verify = |now| {
+            verify_standard_webhooks(
+                secret,
+                body,
+                "msg_p5jXN8AQM9LWM0D4loKWxJek",
+                "1614265330",
+                sig,
+                DEFAULT_TOLERANCE_SECS,
+                now,
+            )
+        };
+        assert_eq!(verify(1614265330), Ok(()));
+        assert_eq!(verify(1614265330 + 301), Err(VerifyError::StaleTimestamp));
+    }
+
+    // Stripe publishes no signed example, so the v1 signature was computed
+    // outside this crate (Python's hmac) over "{t}.{body}", the layout its
+    // signature docs describe.
+    #[test]
+    fn stripe_signature() {
+        let body = br#"{"id":"evt_test_webhook","object":"event"}"#;
+        let header = "t=1492774577,\
+            v1=0000000000000000000000000000000000000000000000000000000000000000,\
+            v1=88a022085c6bdb887b02cb26ff76dd681234d9675c0f22844059f55552a8883a,\
+            v0=6ffbb59b2300aae63f272406069a9788598b792a944a07aba816edb039989a39";
+        let verify = |secret, now| verify_stripe(secret, body, header, DEFAULT_TOLERANCE_SECS, now);
+        assert_eq!(verify("whsec_test_secret", 1492774577), Ok(()));
+        assert_eq!(
+            verify("whsec_other", 1492774577),
+            Err(VerifyError::BadSignature)
+        );
+        assert_eq!(
+            verify("whsec_test_secret", 1492774577 + 600),
+            Err(VerifyError::StaleTimestamp)
+        );
+        assert_eq!(
+            verify_stripe("whsec_test_secret", body, "v1=00", 300, 0),
+            Err(VerifyError::BadTimestamp)
+        );
+    }
+
+    // From Slack's "Verifying requests from Slack" guide.
+    #[test]
+    fn slack_docs_vector() {
+        let body = "token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&team_domain=testteamnow\
+            &channel_id=G8PSS9T3V&channel_name=foobar&user_id=U2CERLKJA&user_name=roadrunner\
+            &command=%2Fwebhook-collect&text=&response_url=https%3A%2F%2Fhooks.slack.com%2F\
+            commands%2FT1DC2JH3J%2F397700885554%2F96rGlfmibIGlgcZRskXaIFfN\
+            &trigger_id=398738663015.47445629121.803a0bc887a14d10d2c447fce8b6703c";
+        let secret = "8f742231b10e8888abcd99yyyzzz85a5";
+        let sig = "v0=a2114d57b48eac39b9ad189dd8316235a7b4a8d21a10bd27519666489c69b503";
+        let verify = |body: &str, now| {
+            verify_slack(secret, body.as_bytes(), "1531420618", sig, DEFAULT_TOLERANCE_SECS, now)
+        };
+        assert_eq!(verify(body, 1531420618), Ok(()));
+        assert_eq!(
+            verify(&body.replace("foobar", "general"), 1531420618),
+            Err(VerifyError::BadSignature)
+        );
+        assert_eq!(verify(body, 1531420618 + 301), Err(VerifyError::StaleTimestamp));
+    }
+
+    // Shopify publishes no signed example; the value was computed outside
+    // this crate (Python's hmac) as base64 of the HMAC of the raw body.
+    #[test]
+    fn shopify_signature() {
+        let body = br#"{"id":820982911946154508}"#;
+        let sig = "qBvrsZF7RfB1iS6BX6IHqJTp5L911P7intnPeTPsH0I=";
+        assert_eq!(verify_shopify("hush", body, sig), Ok(()));
+        assert_eq!(
+            verify_shopify("hush", br#"{"id":1}"#, sig),
+            Err(VerifyError::BadSignature)
+        );
+        assert_eq!(
+            verify_shopify("hush", body, "not base64!"),
+            Err(VerifyError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn gitlab_token() {
+        assert_eq!(verify_custom_header("glpat-secret", "glpat-secret"), Ok(()));
+        assert_eq!(
+            verify_custom_header("glpat-secret", "glpat-secreT"),
+            Err(VerifyError::BadSignature)
+        );
+    }
+
+    // Linear publishes no signed example; the value was computed outside
+    // this crate (Python's hmac) as hex of the HMAC of the raw body.
+    #[test]
+    fn linear_signature() {
+        let body = br#"{"action":"create","type":"Issue","webhookTimestamp":1676056940508}"#;
+        let sig = "7f1b01e2282c7612f4ba310248a964ea16327e81ef037d2f5a0080bf80b4e551";
+        let verify =
+            |secret, now| verify_linear(secret, body, sig, LINEAR_TOLERANCE_SECS, now);
+        assert_eq!(verify("lin_wh_secret", 1676056940), Ok(()));
+        assert_eq!(verify("other", 1676056940), Err(VerifyError::BadSignature));
+        assert_eq!(
+            verify("lin_wh_secret", 1676056940 + 61),
+            Err(VerifyError::StaleTimestamp)
+        );
+    }
+
+    // From Twilio's "Webhooks security" guide.
+    #[test]
+    fn twilio_docs_vector() {
+        let params: Vec<(String, String)> = [
+            ("CallSid", "CA1234567890ABCDE"),
+            ("Caller", "+12349013030"),
+            ("Digits", "1234"),
+            ("From", "+12349013030"),
+            ("To", "+18005551212"),
+        ]
+        .iter()
+        .rev()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let url = "https://mycompany.com/myapp.php?foo=1&bar=2";
+        let sig = "0/KCTR6DLpKmkAf8muzZqo1nDgQ=";
+        assert_eq!(verify_twilio("12345", url, &params, b"", sig), Ok(()));
+        assert_eq!(
+            verify_twilio("12345", "https://mycompany.com/myapp.php", &params, b"", sig),
+            Err(VerifyError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn twilio_json_body_hash() {
+        let body = br#"{"CallSid":"CA1234567890ABCDE","Caller":"+12349013030"}"#;
+        let url = "https://mycompany.com/myapp.php?bodySHA256=\
+            5ccde7145dfb8f56479710896586cb9d5911809d83afbe34627818790db0aec9";
+        let sig = "bz08+n07nApCcdckWZ6FpIblNgo=";
+        assert_eq!(verify_twilio("12345", url, &[], body, sig), Ok(()));
+        assert_eq!(
+            verify_twilio("12345", url, &[], b"{}", sig),
+            Err(VerifyError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn authorization_schemes() {
+        assert_eq!(verify_bearer("tok", "Bearer tok"), Ok(()));
+        assert_eq!(verify_bearer("tok", "bearer  tok"), Ok(()));
+        assert_eq!(verify_bearer("tok", "Basic tok"), Err(VerifyError::BadSignature));
+        // "user:pass"
+        assert_eq!(verify_basic("user:pass", "Basic dXNlcjpwYXNz"), Ok(()));
+        assert_eq!(verify_basic("user:other", "Basic dXNlcjpwYXNz"), Err(VerifyError::BadSignature));
+        assert_eq!(verify_basic("nocolon", "Basic dXNlcjpwYXNz"), Err(VerifyError::MalformedSecret));
+    }
 }